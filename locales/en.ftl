@@ -1,5 +1,10 @@
 ## Time units formatting for the `DURATION` function.
 
+duration-days = { $value ->
+    [one] { $value } day
+   *[many] { $value } days
+}
+
 duration-hours = { $value ->
     [one] { $value } hour
    *[many] { $value } hours
@@ -17,24 +22,93 @@ duration-seconds = { $value ->
 
 ## Bot reply messages.
 
-captcha-caption = { $user_tag } Choose what is shown in the picture. You have { DURATION($duration) }.
+captcha-caption = { $user_tag } { $task } You have { DURATION($duration) }.
 captcha-time-over = { $user_tag } Time is over.
     You can try to join the group again after { DURATION($duration) }.
 captcha-incorrect-answer = { $user_tag } Incorrect answer.
     You can try to join the group again after { DURATION($duration) }.
 
+captcha-task-image = Choose what is shown in the picture.
+captcha-task-text = Choose the answer: <b>{ $question }</b>
+captcha-task-rendered = Choose the answer to the example in the picture.
+
+captcha-caption-retry = { $user_tag } Incorrect answer, attempts left: { $attempts }.
+    { $task }
+
+captcha-join-caption = Solve CAPTCHA to join { $chat_title }. { $task } You have { DURATION($duration) }.
+captcha-join-time-over = Time is over, your join request is declined.
+    You can try to join the group again after { DURATION($duration) }.
+captcha-join-incorrect-answer = Incorrect answer, your join request is declined.
+    You can try to join the group again after { DURATION($duration) }.
+captcha-join-approved = Correct! Your join request is approved.
+
+captcha-private-notice = { $user_tag } To be able to send messages, solve CAPTCHA in private chat with me. You have { DURATION($duration) }.
+captcha-private-button = Solve CAPTCHA
+captcha-private-caption = { $task } Attempts left: { $attempts }.
+captcha-private-not-found = There is no CAPTCHA for you, it may have already expired.
+captcha-private-passed = Correct! Now you can send messages in the group.
+captcha-too-many = Too many CAPTCHAs, try again in a minute.
+
 query-wrong-user = Not your keyboard.
 query-correct = Correct!
 query-wrong = Wrong!
 
+lockdown-started = Too many users are joining the group, it's read-only for { DURATION($duration) }.
+lockdown-finished = The group isn't read-only anymore.
+lockdown-already-started = The group is already read-only.
+lockdown-not-started = The group isn't read-only.
+
+newcomer-message-deleted = { $user_tag } New members can't post links, mentions, media and forwards from channels.
+
+filter-url-deleted = { $user_tag } Links aren't allowed in this group.
+filter-invite-deleted = { $user_tag } Invite links to other chats aren't allowed in this group.
+filter-channel-forward-deleted = { $user_tag } Forwards from channels aren't allowed in this group.
+filter-story-deleted = { $user_tag } Stories aren't allowed in this group.
+filter-contact-deleted = { $user_tag } Contacts aren't allowed in this group.
+
 make-me-admin = Great! Now make me an <b>admin</b>, so I can restrict newcomers until they pass the CAPTCHA 😉
 greeting = { $user_tag } Welcome!
 
 ## Settings related messages.
 
 settings-changed = Settings changed successfully.
-settings-input = Send me settings in the same format as above:
+settings-menu = Choose a setting to change:
     (<a href="https://github.com/crabvk/perception-guardian#bot-settings">Settings description</a>)
+settings-choose-value = Choose a new value of <b>{ $key }</b>, current one is <code>{ $value }</code>:
+settings-input-value = { $user_tag } Send me a new value of <b>{ $key }</b> or /cancel.
+settings-invalid-value = Invalid value: { $error }
+settings-error-unknown-setting = unknown setting "{ $key }"
+settings-error-unknown-value = unknown value "{ $value }"
+settings-error-not-number = value must be a number
+settings-error-not-boolean = value must be true or false
+settings-error-out-of-range = value must be between { $min } and { $max }
+settings-error-invalid-duration = invalid duration "{ $value }", use seconds or units like 90s, 5m, 1h30m, 2d
+settings-error-duration-out-of-range = duration must be between { DURATION($min) } and { DURATION($max) }
+settings-error-invalid-ban-duration = invalid ban duration: { $error }
+settings-error-too-many-rows = captcha_rows must not be greater than captcha_options
+settings-error-row-too-long = captcha_options must fit in captcha_rows of { $size } buttons
+settings-error-no-user-tag = greeting must contain { "{" }user_tag{ "}" }
+settings-error-question-format = question must be in "emoji: phrase" format
+settings-error-empty-emoji = emoji must not be empty
+settings-error-empty-phrase = phrase must not be empty
+settings-error-emoji-too-long = emoji must be at most { $max } bytes long
+settings-error-not-emoji = emoji must be a single emoji
+settings-error-no-picture = question { $emoji } must have either phrase or file_id
+settings-error-emoji-used = emoji { $emoji } is already used in questions
+settings-error-json = invalid JSON: { $error }
+settings-error-file-too-large = file must be at most { $size } bytes
+settings-error-not-utf8 = file must be UTF-8 text
+settings-set-usage = Usage: /set &lt;setting&gt; &lt;value&gt;, e.g. /set captcha_expire 120
+settings-import-usage = Send JSON exported by /settings_export after the command (on the same or the next line), or reply with the command to a message with it.
+settings-import-failed = Settings aren't imported: { $error }
+settings-imported = Settings imported successfully.
+settings-copy-usage = Usage: /settings_copy &lt;group id&gt;, e.g. /settings_copy -1001234567890
+settings-copy-not-admin = You must be an admin of both groups to copy settings.
+settings-value-off = off
+settings-value-ban-for = ban for { DURATION($duration) }
+settings-button-custom = Other value…
+settings-button-back = « Back
+settings-button-close = Close
 settings-greeting-changed = OK, now I'll use new greeting:
 
     { $greeting }
@@ -45,3 +119,11 @@ settings-input-greeting = { $greeting }
 settings-text-required = Send me some text.
 settings-cancel = Setting editing is canceled.
 settings-message-outdated = The message is outdated.
+settings-input-question = Send me a new question as "emoji: search phrase", e.g. "🦀: crab on a beach",
+    or a picture with an emoji in the caption.
+settings-question-added = OK, question { $emoji } added.
+settings-question-exists = Emoji { $emoji } is already used in questions.
+settings-questions-empty = There are no custom questions, use /question to add one.
+settings-question-picture = uploaded picture
+settings-question-deleted = Question deleted.
+settings-question-not-found = Question not found.
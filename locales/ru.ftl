@@ -1,5 +1,11 @@
 ## Time units formatting for the `DURATION` function.
 
+duration-days = { $value ->
+    [one] { $value } день
+    [few] { $value } дня
+   *[many] { $value } дней
+}
+
 duration-hours = { $value ->
     [one] { $value } час
     [few] { $value} часа
@@ -20,24 +26,93 @@ duration-seconds = { $value ->
 
 ## Bot reply messages.
 
-captcha-caption = { $user_tag } Выберите что изображено на картинке. У вас { DURATION($duration) }.
+captcha-caption = { $user_tag } { $task } У вас { DURATION($duration) }.
 captcha-time-over = { $user_tag } Время вышло.
     Вы можете попробовать зайти в группу снова через { DURATION($duration) }.
 captcha-incorrect-answer = { $user_tag } Неправильный ответ.
     Вы можете попробовать зайти в группу снова через { DURATION($duration) }.
 
+captcha-task-image = Выберите что изображено на картинке.
+captcha-task-text = Выберите ответ: <b>{ $question }</b>
+captcha-task-rendered = Выберите ответ на пример с картинки.
+
+captcha-caption-retry = { $user_tag } Неправильный ответ, осталось попыток: { $attempts }.
+    { $task }
+
+captcha-join-caption = Пройдите капчу чтобы вступить в { $chat_title }. { $task } У вас { DURATION($duration) }.
+captcha-join-time-over = Время вышло, ваша заявка на вступление отклонена.
+    Вы можете попробовать зайти в группу снова через { DURATION($duration) }.
+captcha-join-incorrect-answer = Неправильный ответ, ваша заявка на вступление отклонена.
+    Вы можете попробовать зайти в группу снова через { DURATION($duration) }.
+captcha-join-approved = Верно! Ваша заявка на вступление одобрена.
+
+captcha-private-notice = { $user_tag } Чтобы писать сообщения, пройдите капчу в личном чате со мной. У вас { DURATION($duration) }.
+captcha-private-button = Пройти капчу
+captcha-private-caption = { $task } Осталось попыток: { $attempts }.
+captcha-private-not-found = Для вас нет капчи, возможно время уже вышло.
+captcha-private-passed = Верно! Теперь вы можете писать сообщения в группе.
+captcha-too-many = Слишком много капч, попробуйте снова через минуту.
+
 query-wrong-user = Не ваша клавиатура.
 query-correct = Верно!
 query-wrong = Неверно!
 
+lockdown-started = В группу вступает слишком много пользователей, она доступна только для чтения на { DURATION($duration) }.
+lockdown-finished = Группа больше не доступна только для чтения.
+lockdown-already-started = Группа уже доступна только для чтения.
+lockdown-not-started = Группа не в режиме только для чтения.
+
+newcomer-message-deleted = { $user_tag } Новым участникам нельзя публиковать ссылки, упоминания, медиа и пересылать сообщения из каналов.
+
+filter-url-deleted = { $user_tag } Ссылки в этой группе запрещены.
+filter-invite-deleted = { $user_tag } Ссылки-приглашения в другие чаты в этой группе запрещены.
+filter-channel-forward-deleted = { $user_tag } Пересылка из каналов в этой группе запрещена.
+filter-story-deleted = { $user_tag } Истории в этой группе запрещены.
+filter-contact-deleted = { $user_tag } Контакты в этой группе запрещены.
+
 make-me-admin = Отлично! Теперь сделайте меня <b>админом</b> чтобы я мог ограничивать новых пользователей пока они не пройдут капчу 😉
 greeting = { $user_tag } Добро пожаловать!
 
 ## Settings related messages.
 
 settings-changed = Настройки успешно изменены.
-settings-input = Отправьте мне настройки в том же формате, что и выше:
+settings-menu = Выберите настройку, которую нужно изменить:
     (<a href="https://github.com/crabvk/perception-guardian#bot-settings">Описание настроек</a>)
+settings-choose-value = Выберите новое значение <b>{ $key }</b>, текущее <code>{ $value }</code>:
+settings-input-value = { $user_tag } Отправьте мне новое значение <b>{ $key }</b> или /cancel.
+settings-invalid-value = Недопустимое значение: { $error }
+settings-error-unknown-setting = неизвестная настройка "{ $key }"
+settings-error-unknown-value = неизвестное значение "{ $value }"
+settings-error-not-number = значение должно быть числом
+settings-error-not-boolean = значение должно быть true или false
+settings-error-out-of-range = значение должно быть от { $min } до { $max }
+settings-error-invalid-duration = неверная длительность "{ $value }", укажите секунды или единицы, например 90s, 5m, 1h30m, 2d
+settings-error-duration-out-of-range = длительность должна быть от { DURATION($min) } до { DURATION($max) }
+settings-error-invalid-ban-duration = неверный срок бана: { $error }
+settings-error-too-many-rows = captcha_rows не должно быть больше captcha_options
+settings-error-row-too-long = captcha_options должны поместиться в captcha_rows по { $size } кнопок
+settings-error-no-user-tag = приветствие должно содержать { "{" }user_tag{ "}" }
+settings-error-question-format = вопрос должен быть в формате "эмодзи: фраза"
+settings-error-empty-emoji = эмодзи не должен быть пустым
+settings-error-empty-phrase = фраза не должна быть пустой
+settings-error-emoji-too-long = эмодзи должен занимать не больше { $max } байт
+settings-error-not-emoji = эмодзи должен быть одним символом эмодзи
+settings-error-no-picture = у вопроса { $emoji } должна быть фраза или file_id
+settings-error-emoji-used = эмодзи { $emoji } уже используется в вопросах
+settings-error-json = неверный JSON: { $error }
+settings-error-file-too-large = файл должен быть не больше { $size } байт
+settings-error-not-utf8 = файл должен быть текстом в UTF-8
+settings-set-usage = Использование: /set &lt;настройка&gt; &lt;значение&gt;, например /set captcha_expire 120
+settings-import-usage = Отправьте JSON, полученный с помощью /settings_export, после команды (в той же или следующей строке) или ответьте командой на сообщение с ним.
+settings-import-failed = Настройки не импортированы: { $error }
+settings-imported = Настройки успешно импортированы.
+settings-copy-usage = Использование: /settings_copy &lt;id группы&gt;, например /settings_copy -1001234567890
+settings-copy-not-admin = Чтобы скопировать настройки, нужно быть админом обеих групп.
+settings-value-off = выкл.
+settings-value-ban-for = бан на { DURATION($duration) }
+settings-button-custom = Другое значение…
+settings-button-back = « Назад
+settings-button-close = Закрыть
 settings-greeting-changed = OK, теперь я буду использовать новое приветствие:
 
     { $greeting }
@@ -48,3 +123,11 @@ settings-input-greeting = { $greeting }
 settings-text-required = Требуется ввести текст.
 settings-cancel = Редактирование настройки отменено.
 settings-message-outdated = Сообщение устарело.
+settings-input-question = Отправьте мне новый вопрос в формате "эмодзи: поисковая фраза", например "🦀: crab on a beach",
+    или картинку с эмодзи в подписи.
+settings-question-added = OK, вопрос { $emoji } добавлен.
+settings-question-exists = Эмодзи { $emoji } уже используется в вопросах.
+settings-questions-empty = Вопросов пока нет, используйте /question чтобы добавить.
+settings-question-picture = загруженная картинка
+settings-question-deleted = Вопрос удалён.
+settings-question-not-found = Вопрос не найден.
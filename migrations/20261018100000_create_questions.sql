@@ -0,0 +1,8 @@
+CREATE TABLE questions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chat_id INTEGER NOT NULL,
+    emoji TEXT NOT NULL,
+    phrase TEXT,
+    file_id TEXT,
+    UNIQUE (chat_id, emoji)
+);
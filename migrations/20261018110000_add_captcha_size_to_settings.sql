@@ -0,0 +1,2 @@
+ALTER TABLE settings ADD COLUMN captcha_options INTEGER NOT NULL DEFAULT 6;
+ALTER TABLE settings ADD COLUMN captcha_rows INTEGER NOT NULL DEFAULT 2;
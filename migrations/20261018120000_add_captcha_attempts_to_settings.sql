@@ -0,0 +1 @@
+ALTER TABLE settings ADD COLUMN captcha_attempts INTEGER NOT NULL DEFAULT 1;
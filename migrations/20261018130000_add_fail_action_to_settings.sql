@@ -0,0 +1 @@
+ALTER TABLE settings ADD COLUMN fail_action TEXT NOT NULL DEFAULT 'mute';
@@ -0,0 +1 @@
+ALTER TABLE settings ADD COLUMN approve_join_requests BOOLEAN NOT NULL DEFAULT 0;
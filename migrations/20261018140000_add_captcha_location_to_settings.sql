@@ -0,0 +1 @@
+ALTER TABLE settings ADD COLUMN captcha_location TEXT NOT NULL DEFAULT 'group';
@@ -0,0 +1 @@
+ALTER TABLE settings ADD COLUMN captcha_type TEXT NOT NULL DEFAULT 'image';
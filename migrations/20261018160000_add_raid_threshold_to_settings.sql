@@ -0,0 +1 @@
+ALTER TABLE settings ADD COLUMN raid_threshold INTEGER NOT NULL DEFAULT 0;
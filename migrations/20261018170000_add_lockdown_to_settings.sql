@@ -0,0 +1,2 @@
+ALTER TABLE settings ADD COLUMN lockdown_threshold INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE settings ADD COLUMN lockdown_duration INTEGER NOT NULL DEFAULT 600;
@@ -0,0 +1,2 @@
+ALTER TABLE settings ADD COLUMN newcomer_policy TEXT NOT NULL DEFAULT 'off';
+ALTER TABLE settings ADD COLUMN newcomer_messages INTEGER NOT NULL DEFAULT 3;
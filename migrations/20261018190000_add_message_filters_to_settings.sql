@@ -0,0 +1,5 @@
+ALTER TABLE settings ADD COLUMN block_urls BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE settings ADD COLUMN block_invites BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE settings ADD COLUMN block_channel_forwards BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE settings ADD COLUMN block_stories BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE settings ADD COLUMN block_contacts BOOLEAN NOT NULL DEFAULT 0;
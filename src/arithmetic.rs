@@ -0,0 +1,89 @@
+use rand::{seq::SliceRandom, thread_rng, Rng};
+use std::fmt;
+
+/// Operands are small enough to solve an example in mind.
+const MAX_OPERAND: u8 = 9;
+
+/// Arithmetic example asked in text, it doesn't need any picture.
+pub struct Example {
+    pub question: String,
+    pub options: Vec<String>,
+    pub answer: String,
+}
+
+impl Example {
+    pub fn pick(options_amount: usize) -> Example {
+        let mut rng = thread_rng();
+        let a = rng.gen_range(1..=MAX_OPERAND);
+        let b = rng.gen_range(1..=MAX_OPERAND);
+        let (question, answer) = if rng.gen_bool(0.5) {
+            (format!("{a} + {b} = ?"), a + b)
+        } else {
+            let (a, b) = (a.max(b), a.min(b));
+            (format!("{a} - {b} = ?"), a - b)
+        };
+
+        // Results are within 0..=2 * MAX_OPERAND, which is enough for any number of options.
+        let decoys: Vec<u8> = (0..=2 * MAX_OPERAND).filter(|&n| n != answer).collect();
+        let mut options: Vec<String> = decoys
+            .choose_multiple(&mut rng, options_amount - 1)
+            .map(|n| n.to_string())
+            .collect();
+        options.push(answer.to_string());
+        options.shuffle(&mut rng);
+
+        Example {
+            question,
+            options,
+            answer: answer.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Example {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Variants: {:?}, Answer: \"{}\", Question: \"{}\"",
+            self.options, self.answer, self.question
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Solves a question like "7 - 3 = ?".
+    fn solve(question: &str) -> u8 {
+        let expression = question.strip_suffix(" = ?").unwrap();
+        let parts: Vec<_> = expression.split(' ').collect();
+        let (a, b): (u8, u8) = (parts[0].parse().unwrap(), parts[2].parse().unwrap());
+        match parts[1] {
+            "+" => a + b,
+            "-" => a - b,
+            operator => panic!("unexpected operator {operator}"),
+        }
+    }
+
+    #[test]
+    fn answer_matches_question() {
+        for _ in 0..1000 {
+            let example = Example::pick(6);
+            assert_eq!(example.answer, solve(&example.question).to_string());
+        }
+    }
+
+    #[test]
+    fn options_contain_answer_once() {
+        for amount in [2, 6, 12] {
+            let example = Example::pick(amount);
+            assert_eq!(example.options.len(), amount);
+            let mut options = example.options.clone();
+            options.sort();
+            options.dedup();
+            assert_eq!(options.len(), amount);
+            assert!(example.options.contains(&example.answer));
+        }
+    }
+}
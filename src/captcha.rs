@@ -0,0 +1,234 @@
+use crate::arithmetic::Example;
+use crate::images;
+use crate::l10n::Language;
+use crate::qna::{Combination, Picture};
+use crate::settings::{self, CaptchaType, Settings};
+use crate::{render, t, utils};
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use std::{error, fmt, str::FromStr};
+use teloxide::prelude::*;
+use teloxide::types::{
+    ChatId, InlineKeyboardMarkup, InputFile, InputMedia, InputMediaPhoto, Message, ParseMode,
+};
+use teloxide::RequestError;
+
+/// Whether CAPTCHA is shown to a restricted member or to a user who requested to join.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptchaKind {
+    Member,
+    JoinRequest,
+}
+
+impl fmt::Display for CaptchaKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Member => write!(f, "member"),
+            Self::JoinRequest => write!(f, "join_request"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownCaptchaKindError(String);
+
+impl fmt::Display for UnknownCaptchaKindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown captcha kind \"{}\"", self.0)
+    }
+}
+
+impl error::Error for UnknownCaptchaKindError {}
+
+impl FromStr for CaptchaKind {
+    type Err = UnknownCaptchaKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "member" => Ok(Self::Member),
+            "join_request" => Ok(Self::JoinRequest),
+            value => Err(UnknownCaptchaKindError(value.into())),
+        }
+    }
+}
+
+/// What a user is asked to answer.
+pub enum Challenge {
+    /// Picture found by the `phrase`, or uploaded beforehand if it's `None`.
+    Image {
+        image: InputFile,
+        phrase: Option<String>,
+    },
+    /// Picture of the question rendered by the bot.
+    Rendered(InputFile),
+    /// Question shown in the message text.
+    Text(String),
+}
+
+pub struct Captcha {
+    pub options: Vec<String>,
+    pub answer: String,
+    pub challenge: Challenge,
+}
+
+impl Captcha {
+    /// Generates CAPTCHA of chat's type, falls back to a text one when no picture can be made.
+    pub async fn generate(chat_id: ChatId, settings: &Settings) -> Self {
+        let captcha = match settings.captcha_type {
+            CaptchaType::Image => Self::image(chat_id, settings).await,
+            CaptchaType::Rendered => Self::rendered(settings),
+            CaptchaType::Text => return Self::text(settings),
+        };
+        captcha.unwrap_or_else(|error| {
+            log::error!("Falling back to text CAPTCHA: {error}");
+            Self::text(settings)
+        })
+    }
+
+    /// Picks a combination of chat's questions and fetches a picture for the answer.
+    async fn image(chat_id: ChatId, settings: &Settings) -> Result<Self, anyhow::Error> {
+        let questions = settings::get_questions(chat_id);
+        let comb = Combination::pick(settings.captcha_options.into(), &questions)?;
+        log::info!("{comb}");
+
+        let (image, phrase) = match comb.picture {
+            Picture::Phrase(phrase) => (images::get_image(&phrase).await?, Some(phrase)),
+            Picture::File(file_id) => (InputFile::file_id(file_id), None),
+        };
+
+        Ok(Self {
+            options: comb.emojis,
+            answer: comb.answer,
+            challenge: Challenge::Image { image, phrase },
+        })
+    }
+
+    fn rendered(settings: &Settings) -> Result<Self, anyhow::Error> {
+        let example = Example::pick(settings.captcha_options.into());
+        log::info!("{example}");
+        let png = render::render(&example.question, thread_rng().gen())?;
+
+        Ok(Self {
+            options: example.options,
+            answer: example.answer,
+            challenge: Challenge::Rendered(InputFile::memory(png).file_name("captcha.png")),
+        })
+    }
+
+    fn text(settings: &Settings) -> Self {
+        let example = Example::pick(settings.captcha_options.into());
+        log::info!("{example}");
+
+        Self {
+            options: example.options,
+            answer: example.answer,
+            challenge: Challenge::Text(example.question),
+        }
+    }
+
+    /// Localized description of what a user should do, to be included in the caption.
+    pub fn task(&self, lang: Language) -> String {
+        match &self.challenge {
+            Challenge::Image { .. } => t!("captcha-task-image", lang),
+            Challenge::Rendered(_) => t!("captcha-task-rendered", lang),
+            Challenge::Text(question) => t!("captcha-task-text", lang, question = question.clone()),
+        }
+    }
+
+    /// Buttons of CAPTCHA sent in a private chat are prefixed with id of the group `chat_id`.
+    pub fn keyboard(&self, settings: &Settings, chat_id: Option<ChatId>) -> InlineKeyboardMarkup {
+        let prefix = chat_id.map(|id| format!("{id}:")).unwrap_or_default();
+        utils::emojis_keyboard(&self.options, settings.captcha_rows.into(), &prefix)
+    }
+
+    /// Sends CAPTCHA, a picture Telegram fails to send is replaced by a text CAPTCHA,
+    /// so the user isn't left muted without a question. `caption` is made for the CAPTCHA
+    /// actually sent, buttons are prefixed with `group_id` as in `keyboard`.
+    pub async fn send(
+        &mut self,
+        bot: &crate::Bot,
+        chat_id: ChatId,
+        settings: &Settings,
+        group_id: Option<ChatId>,
+        caption: impl Fn(&Self) -> String,
+    ) -> Result<Message, RequestError> {
+        let keyboard = self.keyboard(settings, group_id);
+        match self.send_once(bot, chat_id, caption(self), keyboard).await {
+            Err(error) if self.has_picture() => {
+                log::error!("Falling back to text CAPTCHA, failed to send picture: {error}");
+                *self = Self::text(settings);
+                let keyboard = self.keyboard(settings, group_id);
+                self.send_once(bot, chat_id, caption(self), keyboard).await
+            }
+            result => result,
+        }
+    }
+
+    async fn send_once(
+        &self,
+        bot: &crate::Bot,
+        chat_id: ChatId,
+        caption: String,
+        keyboard: InlineKeyboardMarkup,
+    ) -> Result<Message, RequestError> {
+        match &self.challenge {
+            Challenge::Image { image, .. } | Challenge::Rendered(image) => {
+                bot.send_photo(chat_id, image.clone())
+                    .caption(caption)
+                    .reply_markup(keyboard)
+                    .await
+            }
+            Challenge::Text(_) => {
+                bot.send_message(chat_id, caption)
+                    .reply_markup(keyboard)
+                    .await
+            }
+        }
+    }
+
+    fn has_picture(&self) -> bool {
+        !matches!(self.challenge, Challenge::Text(_))
+    }
+
+    /// Whether CAPTCHA can be shown by editing the `message`, photo can't be replaced by text.
+    pub fn fits(&self, message: &Message) -> bool {
+        self.has_picture() == message.photo().is_some()
+    }
+
+    /// Shows CAPTCHA in place of the one in `message`, check if it `fits` first.
+    pub async fn edit(
+        &self,
+        bot: &crate::Bot,
+        message: &Message,
+        caption: String,
+        keyboard: InlineKeyboardMarkup,
+    ) -> Result<Message, RequestError> {
+        match &self.challenge {
+            Challenge::Image { image, .. } | Challenge::Rendered(image) => {
+                let media = InputMediaPhoto::new(image.clone())
+                    .caption(caption)
+                    .parse_mode(ParseMode::Html);
+                bot.edit_message_media(message.chat.id, message.id, InputMedia::Photo(media))
+                    .reply_markup(keyboard)
+                    .await
+            }
+            Challenge::Text(_) => {
+                bot.edit_message_text(message.chat.id, message.id, caption)
+                    .reply_markup(keyboard)
+                    .await
+            }
+        }
+    }
+
+    /// Caches file_id of the picture from the sent CAPTCHA `message`.
+    pub async fn sent(&self, message: &Message) {
+        if let Challenge::Image {
+            phrase: Some(phrase),
+            ..
+        } = &self.challenge
+        {
+            images::cache(phrase, message).await;
+        }
+    }
+}
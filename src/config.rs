@@ -1,10 +1,20 @@
-use std::{convert::From, env, error, fmt, net::SocketAddr};
+use crate::images::ProviderKind;
+use crate::storage::StorageKind;
+use std::{
+    convert::From, env, error, fmt, net::SocketAddr, num::NonZeroU64, path::PathBuf, time::Duration,
+};
 
 pub struct Config {
     pub token: String,
     pub webhook_host: Option<String>,
     pub webhook_addr: Option<SocketAddr>,
     pub redis_url: url::Url,
+    pub image_providers: Vec<ProviderKind>,
+    pub images_dir: PathBuf,
+    pub image_cache_size: usize,
+    pub qna_path: Option<PathBuf>,
+    pub dialogue_storage: StorageKind,
+    pub dialogue_ttl: Duration,
 }
 
 #[derive(Debug)]
@@ -112,11 +122,109 @@ impl Config {
                     error: error.into(),
                 })?;
 
+        let image_providers = match env::var("IMAGE_PROVIDERS") {
+            Ok(providers) => providers
+                .split(',')
+                .map(|kind| kind.trim().parse::<ProviderKind>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|error| ConfigError::InvalidValue {
+                    key: "IMAGE_PROVIDERS",
+                    value: providers.clone(),
+                    error: error.into(),
+                })?,
+            Err(env::VarError::NotPresent) => vec![ProviderKind::Qwant],
+            Err(error) => {
+                return Err(ConfigError::EnvVar {
+                    key: "IMAGE_PROVIDERS",
+                    error,
+                })
+            }
+        };
+
+        let images_dir = match env::var("IMAGES_DIR") {
+            Ok(dir) => PathBuf::from(dir),
+            Err(env::VarError::NotPresent) => PathBuf::from("images"),
+            Err(error) => {
+                return Err(ConfigError::EnvVar {
+                    key: "IMAGES_DIR",
+                    error,
+                })
+            }
+        };
+
+        let image_cache_size = match env::var("IMAGE_CACHE_SIZE") {
+            Ok(size) => size
+                .parse::<usize>()
+                .map_err(|error| ConfigError::InvalidValue {
+                    key: "IMAGE_CACHE_SIZE",
+                    value: size,
+                    error: error.into(),
+                })?,
+            Err(env::VarError::NotPresent) => 10,
+            Err(error) => {
+                return Err(ConfigError::EnvVar {
+                    key: "IMAGE_CACHE_SIZE",
+                    error,
+                })
+            }
+        };
+
+        let qna_path = match env::var("QNA_PATH") {
+            Ok(path) => Some(PathBuf::from(path)),
+            Err(env::VarError::NotPresent) => None,
+            Err(error) => {
+                return Err(ConfigError::EnvVar {
+                    key: "QNA_PATH",
+                    error,
+                })
+            }
+        };
+
+        let dialogue_storage = match env::var("DIALOGUE_STORAGE") {
+            Ok(kind) => kind
+                .parse::<StorageKind>()
+                .map_err(|error| ConfigError::InvalidValue {
+                    key: "DIALOGUE_STORAGE",
+                    value: kind,
+                    error: error.into(),
+                })?,
+            Err(env::VarError::NotPresent) => StorageKind::Memory,
+            Err(error) => {
+                return Err(ConfigError::EnvVar {
+                    key: "DIALOGUE_STORAGE",
+                    error,
+                })
+            }
+        };
+
+        let dialogue_ttl = match env::var("DIALOGUE_TTL") {
+            Ok(ttl) => ttl
+                .parse::<NonZeroU64>()
+                .map_err(|error| ConfigError::InvalidValue {
+                    key: "DIALOGUE_TTL",
+                    value: ttl,
+                    error: error.into(),
+                })?,
+            Err(env::VarError::NotPresent) => NonZeroU64::new(3600).unwrap(),
+            Err(error) => {
+                return Err(ConfigError::EnvVar {
+                    key: "DIALOGUE_TTL",
+                    error,
+                })
+            }
+        };
+
         Ok(Config {
             token,
             webhook_host,
             webhook_addr,
             redis_url,
+            image_providers,
+            images_dir,
+            image_cache_size,
+            qna_path,
+            dialogue_storage,
+            dialogue_ttl: Duration::from_secs(dialogue_ttl.get()),
         })
     }
 }
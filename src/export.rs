@@ -0,0 +1,242 @@
+use crate::l10n::Language;
+use crate::qna::{self, Picture};
+use crate::settings::{
+    self, LocalizedError, RawGreeting, RawQuestion, RawQuestionError, RawSetting, RawSettingError,
+    KEYS,
+};
+use crate::t;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::{error, fmt};
+use teloxide::types::ChatId;
+
+/// Settings of a chat with its greeting and custom questions, as exported to admins.
+/// Imported greeting and questions replace the ones the chat had.
+#[derive(Serialize, Deserialize)]
+pub struct Document {
+    /// Values in the same form as accepted by `/set`, missing settings are left unchanged.
+    pub settings: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub greeting: Option<String>,
+    #[serde(default)]
+    pub questions: Vec<Question>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Question {
+    pub emoji: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phrase: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<String>,
+}
+
+/// Document checked to be applied to a chat.
+pub struct Import {
+    pub settings: Vec<RawSetting>,
+    pub greeting: Option<String>,
+    pub questions: Vec<(String, Picture)>,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Json(serde_json::Error),
+    Setting(RawSettingError),
+    Greeting(settings::UserTagNotPresentError),
+    Question(RawQuestionError),
+    NoPicture(String),
+    EmojiUsed(String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ImportError as Error;
+        match self {
+            Error::Json(error) => write!(f, "invalid JSON: {error}"),
+            Error::Setting(error) => write!(f, "{error}"),
+            Error::Greeting(error) => write!(f, "{error}"),
+            Error::Question(error) => write!(f, "{error}"),
+            Error::NoPicture(emoji) => {
+                write!(f, "question {emoji} must have either phrase or file_id")
+            }
+            Error::EmojiUsed(emoji) => write!(f, "emoji {emoji} is already used in questions"),
+        }
+    }
+}
+
+impl error::Error for ImportError {}
+
+impl LocalizedError for ImportError {
+    fn localize(&self, lang: Language) -> String {
+        use ImportError as Error;
+        match self {
+            Error::Json(error) => t!("settings-error-json", lang, error = error.to_string()),
+            Error::Setting(error) => error.localize(lang),
+            Error::Greeting(error) => error.localize(lang),
+            Error::Question(error) => error.localize(lang),
+            Error::NoPicture(emoji) => {
+                t!("settings-error-no-picture", lang, emoji = emoji.as_str())
+            }
+            Error::EmojiUsed(emoji) => {
+                t!("settings-error-emoji-used", lang, emoji = emoji.as_str())
+            }
+        }
+    }
+}
+
+pub fn export(chat_id: ChatId) -> Document {
+    let settings = settings::get(chat_id);
+    let values = KEYS
+        .iter()
+        .filter_map(|key| Some((key.to_string(), RawSetting::value(&settings, key)?)))
+        .collect();
+    let questions = settings::get_questions(chat_id)
+        .into_iter()
+        .map(|question| {
+            let (phrase, file_id) = match question.picture {
+                Picture::Phrase(phrase) => (Some(phrase), None),
+                Picture::File(file_id) => (None, Some(file_id)),
+            };
+            Question {
+                emoji: question.emoji,
+                phrase,
+                file_id,
+            }
+        })
+        .collect();
+
+    Document {
+        settings: values,
+        greeting: settings::get_greeting(chat_id),
+        questions,
+    }
+}
+
+pub fn parse(json: &str) -> Result<Import, ImportError> {
+    let document = serde_json::from_str(json).map_err(ImportError::Json)?;
+    check(document)
+}
+
+/// Checks the document the same way as settings, greeting and questions changed one by one.
+pub fn check(document: Document) -> Result<Import, ImportError> {
+    let settings = document
+        .settings
+        .iter()
+        .map(|(key, value)| RawSetting::parse(key, value))
+        .collect::<Result<_, _>>()
+        .map_err(ImportError::Setting)?;
+
+    let greeting = match document.greeting {
+        Some(greeting) => {
+            let greeting = greeting.parse::<RawGreeting>();
+            Some(greeting.map_err(ImportError::Greeting)?.0)
+        }
+        None => None,
+    };
+
+    let mut questions: Vec<(String, Picture)> = Vec::new();
+    for question in document.questions {
+        let emoji = RawQuestion::parse_emoji(&question.emoji).map_err(ImportError::Question)?;
+        let picture = match (question.phrase, question.file_id) {
+            (_, Some(file_id)) => Picture::File(file_id),
+            (Some(phrase), None) if !phrase.trim().is_empty() => {
+                Picture::Phrase(phrase.trim().to_owned())
+            }
+            _ => return Err(ImportError::NoPicture(emoji)),
+        };
+        let is_used = qna::has_emoji(&emoji)
+            || questions
+                .iter()
+                .any(|(used, _)| qna::same_emoji(used, &emoji));
+        if is_used {
+            return Err(ImportError::EmojiUsed(emoji));
+        }
+        questions.push((emoji, picture));
+    }
+
+    Ok(Import {
+        settings,
+        greeting,
+        questions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_json(json: serde_json::Value) -> Result<Import, ImportError> {
+        check(serde_json::from_value(json).unwrap())
+    }
+
+    #[test]
+    fn check_valid_document() {
+        let import = check_json(serde_json::json!({
+            "settings": {"captcha_expire": "2m", "fail_action": "ban_for:1d"},
+            "greeting": "{user_tag} hi",
+            "questions": [
+                {"emoji": "🦭", "phrase": " seal "},
+                {"emoji": "🪼", "file_id": "file-1"},
+            ],
+        }))
+        .unwrap();
+        assert_eq!(import.settings.len(), 2);
+        assert_eq!(import.greeting.as_deref(), Some("{user_tag} hi"));
+        assert_eq!(import.questions.len(), 2);
+        assert!(matches!(&import.questions[0].1, Picture::Phrase(phrase) if phrase == "seal"));
+    }
+
+    #[test]
+    fn check_invalid_setting() {
+        let result = check_json(serde_json::json!({"settings": {"captcha_options": "100"}}));
+        assert!(matches!(
+            result,
+            Err(ImportError::Setting(RawSettingError::InvalidValue(_)))
+        ));
+
+        let result = check_json(serde_json::json!({"settings": {"unknown": "1"}}));
+        assert!(matches!(
+            result,
+            Err(ImportError::Setting(RawSettingError::UnknownSetting(key))) if key == "unknown"
+        ));
+    }
+
+    #[test]
+    fn check_greeting_without_user_tag() {
+        let result = check_json(serde_json::json!({"settings": {}, "greeting": "hi"}));
+        assert!(matches!(result, Err(ImportError::Greeting(_))));
+    }
+
+    #[test]
+    fn check_question_without_picture() {
+        for question in [
+            serde_json::json!({"emoji": "🦭"}),
+            serde_json::json!({"emoji": "🦭", "phrase": "  "}),
+        ] {
+            let result = check_json(serde_json::json!({"settings": {}, "questions": [question]}));
+            assert!(matches!(result, Err(ImportError::NoPicture(emoji)) if emoji == "🦭"));
+        }
+    }
+
+    #[test]
+    fn check_duplicate_question_emoji() {
+        let result = check_json(serde_json::json!({
+            "settings": {},
+            "questions": [
+                {"emoji": "🦭", "phrase": "seal"},
+                {"emoji": "🦭\u{fe0f}", "phrase": "fur seal"},
+            ],
+        }));
+        assert!(matches!(result, Err(ImportError::EmojiUsed(emoji)) if emoji == "🦭\u{fe0f}"));
+    }
+
+    #[tokio::test]
+    async fn check_catalog_question_emoji() {
+        qna::load(None).await.unwrap();
+        let result = check_json(serde_json::json!({
+            "settings": {},
+            "questions": [{"emoji": "😎", "phrase": "cool cat"}],
+        }));
+        assert!(matches!(result, Err(ImportError::EmojiUsed(emoji)) if emoji == "😎"));
+    }
+}
@@ -0,0 +1,158 @@
+use crate::{library::Library, qwant::Qwant, redis};
+use futures::future::BoxFuture;
+use rand::Rng;
+use std::{error, fmt, path::Path, str::FromStr, sync::OnceLock};
+use teloxide::types::{InputFile, Message};
+
+static IMAGES: OnceLock<Images> = OnceLock::new();
+/// Each query phrase keeps at most this many times `cache_size` of file_ids.
+const CACHE_LIMIT_FACTOR: usize = 5;
+/// Providers are still asked with this probability when enough file_ids are cached,
+/// so the cache keeps growing up to its limit and old pictures are replaced.
+const CACHE_REFRESH_PROBABILITY: f64 = 0.2;
+
+struct Images {
+    providers: Vec<Box<dyn ImageProvider>>,
+    cache_size: usize,
+}
+
+/// Source of CAPTCHA pictures for a query phrase.
+pub trait ImageProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn get_image<'a>(&'a self, query: &'a str) -> BoxFuture<'a, Result<InputFile, anyhow::Error>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderKind {
+    Qwant,
+    Local,
+}
+
+impl fmt::Display for ProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Qwant => write!(f, "qwant"),
+            Self::Local => write!(f, "local"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownProviderError(String);
+
+impl fmt::Display for UnknownProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown image provider \"{}\"", self.0)
+    }
+}
+
+impl error::Error for UnknownProviderError {}
+
+impl FromStr for ProviderKind {
+    type Err = UnknownProviderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "qwant" => Ok(Self::Qwant),
+            "local" => Ok(Self::Local),
+            value => Err(UnknownProviderError(value.into())),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct NoImageError;
+
+impl fmt::Display for NoImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no image provider returned an image")
+    }
+}
+
+impl error::Error for NoImageError {}
+
+pub fn setup(kinds: &[ProviderKind], images_dir: &Path, cache_size: usize) {
+    let providers = kinds
+        .iter()
+        .map(|kind| -> Box<dyn ImageProvider> {
+            match kind {
+                ProviderKind::Qwant => Box::new(Qwant),
+                ProviderKind::Local => Box::new(Library::new(images_dir.to_path_buf())),
+            }
+        })
+        .collect();
+
+    let images = Images {
+        providers,
+        cache_size,
+    };
+
+    if IMAGES.set(images).is_err() {
+        panic!("Couldn't set IMAGES cell.");
+    }
+}
+
+async fn get_cached(query: &str, min_size: usize) -> Option<InputFile> {
+    match redis::random_file_id(query, min_size).await {
+        Ok(file_id) => file_id.map(|file_id| {
+            log::info!("Cached file_id: {file_id}");
+            InputFile::file_id(file_id)
+        }),
+        Err(error) => {
+            log::warn!("Failed to get cached file_id: {error}");
+            None
+        }
+    }
+}
+
+/// Usually uses a cached Telegram file_id when the query has enough of them,
+/// otherwise asks providers in the configured order, falling back to the next one on error.
+/// When all providers fail, any cached file_id is used.
+pub async fn get_image(query: &str) -> Result<InputFile, anyhow::Error> {
+    let images = IMAGES.get().unwrap();
+
+    let refresh = rand::thread_rng().gen_bool(CACHE_REFRESH_PROBABILITY);
+    if images.cache_size > 0 && !refresh {
+        if let Some(image) = get_cached(query, images.cache_size).await {
+            return Ok(image);
+        }
+    }
+
+    let mut last_error = None;
+
+    for provider in &images.providers {
+        match provider.get_image(query).await {
+            Ok(image) => return Ok(image),
+            Err(error) => {
+                log::warn!("Image provider {} failed: {error}", provider.name());
+                last_error = Some(error);
+            }
+        }
+    }
+
+    if images.cache_size > 0 {
+        if let Some(image) = get_cached(query, 1).await {
+            return Ok(image);
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| NoImageError.into()))
+}
+
+/// Saves file_id of the photo sent with the query for later reuse.
+pub async fn cache(query: &str, message: &Message) {
+    let cache_size = IMAGES.get().unwrap().cache_size;
+    if cache_size == 0 {
+        return;
+    }
+
+    // The last photo size is the largest one.
+    if let Some(photo) = message.photo().and_then(|sizes| sizes.last()) {
+        if let Err(error) =
+            redis::add_file_id(query, &photo.file.id, cache_size * CACHE_LIMIT_FACTOR).await
+        {
+            log::warn!("Failed to cache file_id: {error}");
+        }
+    }
+}
@@ -34,6 +34,12 @@ impl fmt::Display for UnknownLanguageError {
 
 impl error::Error for UnknownLanguageError {}
 
+impl UnknownLanguageError {
+    pub fn value(&self) -> &str {
+        &self.0
+    }
+}
+
 impl FromStr for Language {
     type Err = UnknownLanguageError;
 
@@ -75,7 +81,7 @@ pub async fn load_locales() -> Result<(), anyhow::Error> {
             .expect("Failed to add FTL resources to the bundle.");
         bundle
             .add_function("DURATION", |args, _| {
-                if let Some(FluentValue::Number(FluentNumber { value, .. })) = args.get(0) {
+                if let Some(FluentValue::Number(FluentNumber { value, .. })) = args.first() {
                     return format_duration(*lang, *value as u64).into();
                 }
 
@@ -101,12 +107,16 @@ pub fn translate(message: &str, lang: Language, args: Option<&FluentArgs<'_>>) -
     value.to_string()
 }
 
-fn format_duration(lang: Language, secs: u64) -> String {
-    let hours = secs / 3600;
+pub fn format_duration(lang: Language, secs: u64) -> String {
+    let days = secs / 86400;
+    let hours = secs % 86400 / 3600;
     let minutes = secs % 3600 / 60;
     let seconds = secs % 3600 % 60;
 
     let mut result = vec![];
+    if days > 0 {
+        result.push(t!("duration-days", lang, value = days));
+    }
     if hours > 0 {
         result.push(t!("duration-hours", lang, value = hours));
     }
@@ -124,7 +134,7 @@ macro_rules! t {
     // t!("message-id", lang)
     ($id:expr, $lang:expr) => {
         {
-            crate::l10n::translate($id, $lang, None)
+            $crate::l10n::translate($id, $lang, None)
         }
     };
 
@@ -136,7 +146,7 @@ macro_rules! t {
                 let arg_key = stringify!($arg_key_val);
                 args.set(arg_key, $arg_key_val);
             )*
-            crate::l10n::translate($id, $lang, Some(&args))
+            $crate::l10n::translate($id, $lang, Some(&args))
         }
     };
 
@@ -148,7 +158,7 @@ macro_rules! t {
                 let arg_key = stringify!($arg_key);
                 args.set(arg_key, $arg_val);
             )*
-            crate::l10n::translate($id, $lang, Some(&args))
+            $crate::l10n::translate($id, $lang, Some(&args))
         }
     };
 }
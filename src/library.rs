@@ -0,0 +1,99 @@
+use crate::images::ImageProvider;
+use futures::future::BoxFuture;
+use rand::{seq::SliceRandom, thread_rng};
+use std::{fmt, path::PathBuf};
+use teloxide::types::InputFile;
+use tokio::fs;
+
+const EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+#[derive(Debug)]
+struct NoLocalImageError(PathBuf);
+
+impl fmt::Display for NoLocalImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no images found in {}", self.0.display())
+    }
+}
+
+impl std::error::Error for NoLocalImageError {}
+
+/// Serves images from `<dir>/<phrase-slug>/`, e.g. "images/spider-web/1.jpg".
+pub struct Library {
+    dir: PathBuf,
+}
+
+impl Library {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+pub fn slug(phrase: &str) -> String {
+    phrase
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+async fn get_image_path(dir: PathBuf) -> Result<PathBuf, anyhow::Error> {
+    let mut paths = vec![];
+    let mut entries = fs::read_dir(&dir).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+        if let Some(extension) = extension {
+            if EXTENSIONS.contains(&extension.as_str()) && entry.file_type().await?.is_file() {
+                paths.push(path);
+            }
+        }
+    }
+
+    paths
+        .choose(&mut thread_rng())
+        .cloned()
+        .ok_or_else(|| NoLocalImageError(dir).into())
+}
+
+impl ImageProvider for Library {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn get_image<'a>(&'a self, query: &'a str) -> BoxFuture<'a, Result<InputFile, anyhow::Error>> {
+        Box::pin(async move {
+            let path = get_image_path(self.dir.join(slug(query))).await?;
+            log::info!("Image path: {}", path.display());
+            Ok(InputFile::file(path))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs() {
+        for (phrase, expected) in [
+            ("spider web", "spider-web"),
+            ("Crab on a Beach", "crab-on-a-beach"),
+            ("  people,  on a party! ", "people-on-a-party"),
+            ("rock'n'roll", "rock-n-roll"),
+            ("t-rex", "t-rex"),
+            ("Ёжик в тумане", "ёжик-в-тумане"),
+            ("Straße über Brücke", "straße-über-brücke"),
+            ("crab 🦀 claws", "crab-claws"),
+            ("2 cats", "2-cats"),
+            ("?!", ""),
+        ] {
+            assert_eq!(slug(phrase), expected, "{phrase}");
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use crate::{redis, settings, t, utils};
+use std::time::{Duration, SystemTime};
+use std::{error, fmt};
+use teloxide::prelude::*;
+use teloxide::types::ChatPermissions;
+
+/// Delay before lifting lockdown again if permissions couldn't be restored.
+const LIFT_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct NoPermissionsError;
+
+impl fmt::Display for NoPermissionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "chat permissions are unknown, they couldn't be restored after lockdown"
+        )
+    }
+}
+
+impl error::Error for NoPermissionsError {}
+
+/// Makes the chat read-only for `duration`, returns `false` if it's already locked down.
+pub async fn start(
+    bot: &crate::Bot,
+    chat_id: ChatId,
+    duration: Duration,
+) -> Result<bool, anyhow::Error> {
+    // Don't request the chat on every join during a raid.
+    if redis::get_lockdown(chat_id).await?.is_some() {
+        return Ok(false);
+    }
+
+    // Fallback to all permissions isn't acceptable here, it would be restored after lockdown.
+    let chat = bot.get_chat(chat_id).await?;
+    let permissions = chat.permissions().ok_or(NoPermissionsError)?;
+    if !redis::start_lockdown(chat_id, &permissions, SystemTime::now() + duration).await? {
+        return Ok(false);
+    }
+
+    if let Err(error) = bot
+        .set_chat_permissions(chat_id, ChatPermissions::empty())
+        .await
+    {
+        redis::end_lockdown(chat_id).await?;
+        return Err(error.into());
+    }
+    log::warn!("Chat {chat_id} is locked down");
+
+    let settings = settings::get(chat_id);
+    let text = t!(
+        "lockdown-started",
+        settings.language,
+        duration = duration.as_secs()
+    );
+    bot.send_message(chat_id, text).await?;
+
+    Ok(true)
+}
+
+/// Restores permissions the chat had before lockdown, returns `false` if it isn't locked down.
+pub async fn lift(bot: &crate::Bot, chat_id: ChatId) -> Result<bool, anyhow::Error> {
+    let Some(permissions) = redis::get_lockdown(chat_id).await? else {
+        return Ok(false);
+    };
+    // Permissions are kept until they are restored, the scheduler retries lifting later.
+    if let Err(error) = bot.set_chat_permissions(chat_id, permissions).await {
+        redis::delay_lockdown(chat_id, SystemTime::now() + LIFT_RETRY_DELAY).await?;
+        return Err(error.into());
+    }
+    redis::end_lockdown(chat_id).await?;
+    log::info!("Chat {chat_id} lockdown is lifted");
+
+    let settings = settings::get(chat_id);
+    let text = t!("lockdown-finished", settings.language);
+    let message = bot.send_message(chat_id, text).await?;
+    utils::delete_message_later(bot, chat_id, message.id, settings.message_expire());
+
+    Ok(true)
+}
@@ -1,46 +1,77 @@
+mod arithmetic;
+mod captcha;
 mod config;
+mod export;
+mod images;
 mod l10n;
+mod library;
+mod lockdown;
+mod menu;
 mod qna;
 mod qwant;
 mod redis;
+mod render;
+mod scheduler;
 mod settings;
+mod spam;
+mod storage;
 mod utils;
 
+use crate::captcha::{Captcha, CaptchaKind};
 use crate::config::Config;
-use crate::settings::{BanChannels, RawGreeting, RawSetting};
-use std::{collections::HashMap, future::IntoFuture};
+use crate::qna::Picture;
+use crate::settings::{
+    BanChannels, CaptchaLocation, FailAction, LocalizedError, NewcomerPolicy, RawGreeting,
+    RawQuestion, RawSetting, RawSettingError, Settings,
+};
+use crate::storage::DialogueStorage;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, future::IntoFuture, sync::Arc};
 use strfmt::strfmt;
 use teloxide::{
-    adaptors::DefaultParseMode,
-    dispatching::{dialogue, dialogue::InMemStorage, UpdateHandler},
+    adaptors::{throttle::Limits, DefaultParseMode, Throttle},
+    dispatching::{dialogue, UpdateHandler},
+    net::Download,
     prelude::*,
     types::{
-        ChatKind, ChatPermissions, ChatPublic, InputFile, Me, ParseMode, PublicChatKind,
-        PublicChatSupergroup, Update, User, UserId,
+        AllowedUpdate, ChatJoinRequest, ChatKind, ChatMemberUpdated, ChatPermissions, ChatPublic,
+        InlineKeyboardButton, InlineKeyboardMarkup, InputFile, Me, MessageId, ParseMode,
+        PublicChatKind, PublicChatSupergroup, Update, UpdateKind, User, UserId,
     },
     update_listeners::UpdateListener,
-    update_listeners::{polling_default, webhooks},
+    update_listeners::{webhooks, Polling},
     utils::{command::BotCommands, html},
+    ApiError, RequestError,
 };
 
-type Bot = DefaultParseMode<teloxide::prelude::Bot>;
-type SettingsDialogue = Dialogue<SettingsState, InMemStorage<SettingsState>>;
+type Bot = DefaultParseMode<Throttle<teloxide::prelude::Bot>>;
+type SettingsDialogue = Dialogue<SettingsState, DialogueStorage<SettingsState>>;
 type HandlerResult = Result<(), anyhow::Error>;
 
-#[derive(Default, Clone)]
+/// More CAPTCHAs per minute for a user look like a flood.
+const MAX_USER_CAPTCHAS: u64 = 5;
+/// Exported settings are a few kilobytes even with many custom questions.
+const MAX_IMPORT_SIZE: u32 = 64 * 1024;
+
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub enum SettingsState {
     #[default]
     Start,
-    Settings {
+    Setting {
         user_id: UserId,
+        key: String,
+        menu_id: MessageId,
     },
     Greeting {
         user_id: UserId,
     },
+    Question {
+        user_id: UserId,
+    },
 }
 
 #[derive(BotCommands, Clone)]
-#[command(rename_rule = "camelCase")]
+#[command(rename_rule = "snake_case")]
 enum Command {
     #[command(description = "display this text")]
     Help,
@@ -48,6 +79,30 @@ enum Command {
     Settings,
     #[command(description = "change greeting of newcomers")]
     Greeting,
+    #[command(description = "add custom CAPTCHA question")]
+    Question,
+    #[command(description = "list custom CAPTCHA questions")]
+    Questions,
+    #[command(description = "delete custom CAPTCHA question by number")]
+    DeleteQuestion(i64),
+    #[command(description = "show value of a setting, or all of them without arguments")]
+    Get(String),
+    #[command(description = "change a setting, e.g. /set captcha_expire 120")]
+    Set(String),
+    #[command(description = "restore default value of a setting")]
+    Reset(String),
+    #[command(description = "export settings, greeting and questions as JSON")]
+    SettingsExport,
+    #[command(
+        description = "import settings from JSON given after the command or in the replied message"
+    )]
+    SettingsImport(String),
+    #[command(description = "copy settings from another group by its id")]
+    SettingsCopy(String),
+    #[command(description = "make the group read-only for a while")]
+    Lockdown,
+    #[command(description = "lift lockdown of the group")]
+    Unlock,
     #[command(description = "cancel changing settings or greeting")]
     Cancel,
 }
@@ -63,40 +118,86 @@ async fn main() -> anyhow::Result<()> {
         .await
         .expect("Couldn't preload Fluent bundles");
 
+    // Load CAPTCHA questions catalog, reload it on SIGHUP when read from a file.
+    let groups = qna::load(config.qna_path.as_deref())
+        .await
+        .expect("Couldn't load questions catalog");
+    log::info!("Loaded {groups} groups of questions");
+    if let Some(path) = config.qna_path.clone() {
+        tokio::spawn(qna::reload_on_hangup(path));
+    }
+
     // Setup Redis connection manager.
     redis::setup(config.redis_url)
         .await
         .expect("Couldn't create Redis connection manager");
 
+    // Setup image providers in the configured fallback order.
+    images::setup(
+        &config.image_providers,
+        &config.images_dir,
+        config.image_cache_size,
+    );
+
     // Preload all settings from SQLite database.
     settings::preload()
         .await
         .expect("Couldn't preload settings");
 
+    let storage = DialogueStorage::new(config.dialogue_storage, config.dialogue_ttl);
+    log::info!("Keeping dialogues in {} storage", config.dialogue_storage);
+
     let token = &config.token;
-    let bot = teloxide::prelude::Bot::new(token).parse_mode(ParseMode::Html);
+    // All requests wait for Telegram flood limits, which is crucial when many users join at once.
+    let bot = teloxide::prelude::Bot::new(token)
+        .throttle(Limits::default())
+        .parse_mode(ParseMode::Html);
+
+    // Expire pending CAPTCHAs, including ones left from the previous run.
+    scheduler::start(bot.clone());
 
     if let Some(ref host) = config.webhook_host {
         log::info!("Receiving updates via webhook on {}", host);
         let addr = config.webhook_addr.unwrap();
-        let url = format!("https://{host}/webhook").parse().unwrap();
-        let opts = webhooks::Options::new(addr, url).secret_token(token.replace(":", "_"));
+        let url: url::Url = format!("https://{host}/webhook").parse().unwrap();
+        // `set_webhook` made by teloxide keeps previously set allowed updates.
+        bot.set_webhook(url.clone())
+            .allowed_updates(allowed_updates())
+            .secret_token(token.replace(':', "_"))
+            .await
+            .expect("Couldn't set allowed updates");
+        let opts = webhooks::Options::new(addr, url).secret_token(token.replace(':', "_"));
         let listener = webhooks::axum(bot.clone(), opts)
             .await
             .expect("Couldn't setup webhook");
-        build_dispatcher(bot, schema(), listener).await;
+        build_dispatcher(bot, schema(), storage, listener).await;
     } else {
         log::info!("Using long polling to fetch updates");
-        let listener = polling_default(bot.clone()).await;
-        build_dispatcher(bot, schema(), listener).await;
+        let listener = Polling::builder(bot.clone())
+            .allowed_updates(allowed_updates())
+            .delete_webhook()
+            .await
+            .build();
+        build_dispatcher(bot, schema(), storage, listener).await;
     };
 
     Ok(())
 }
 
+/// `chat_member` and `chat_join_request` updates aren't sent by Telegram unless explicitly requested.
+fn allowed_updates() -> Vec<AllowedUpdate> {
+    vec![
+        AllowedUpdate::Message,
+        AllowedUpdate::CallbackQuery,
+        AllowedUpdate::ChatMember,
+        AllowedUpdate::ChatJoinRequest,
+    ]
+}
+
 async fn build_dispatcher<UListener>(
     bot: Bot,
     handler: UpdateHandler<anyhow::Error>,
+    storage: Arc<DialogueStorage<SettingsState>>,
     update_listener: UListener,
 ) where
     UListener: UpdateListener,
@@ -104,7 +205,7 @@ async fn build_dispatcher<UListener>(
 {
     let error_handler = LoggingErrorHandler::with_custom_text("An error from the update listener");
     Dispatcher::builder(bot, handler)
-        .dependencies(dptree::deps![InMemStorage::<SettingsState>::new()])
+        .dependencies(dptree::deps![storage])
         .default_handler(|_upd| async move {
             // log::warn!("Unhandled update: {:?}", upd);
         })
@@ -117,10 +218,25 @@ async fn build_dispatcher<UListener>(
         .await;
 }
 
+/// Like `teloxide::filter_command`, but the command may be followed by any whitespace,
+/// e.g. by a newline when its arguments are on the next line.
+fn parse_command(msg: Message, me: Me) -> Option<Command> {
+    let bot_name = me.user.username.expect("Bots must have a username");
+    Command::parse(&normalize_command(msg.text()?), &bot_name).ok()
+}
+
+/// Separates the command from its arguments by a space, as expected by `BotCommands::parse`.
+fn normalize_command(text: &str) -> String {
+    match text.split_once(char::is_whitespace) {
+        Some((command, args)) => format!("{command} {}", args.trim_start()),
+        None => text.to_owned(),
+    }
+}
+
 fn schema() -> UpdateHandler<anyhow::Error> {
     use dptree::case;
 
-    let command_handler = teloxide::filter_command::<Command, _>()
+    let command_handler = dptree::filter_map(parse_command)
         .branch(case![Command::Help].endpoint(help_command_handler))
         .filter_async(is_user_privileged)
         .branch(
@@ -131,21 +247,76 @@ fn schema() -> UpdateHandler<anyhow::Error> {
             case![SettingsState::Start]
                 .branch(case![Command::Greeting].endpoint(greeting_command_handler)),
         )
+        .branch(
+            case![SettingsState::Start]
+                .branch(case![Command::Question].endpoint(question_command_handler)),
+        )
+        .branch(case![Command::Questions].endpoint(questions_command_handler))
+        .branch(case![Command::DeleteQuestion(id)].endpoint(delete_question_command_handler))
+        .branch(case![Command::Get(key)].endpoint(get_command_handler))
+        .branch(case![Command::Set(args)].endpoint(set_command_handler))
+        .branch(case![Command::Reset(key)].endpoint(reset_command_handler))
+        .branch(case![Command::SettingsExport].endpoint(settings_export_command_handler))
+        .branch(case![Command::SettingsImport(json)].endpoint(settings_import_command_handler))
+        .branch(case![Command::SettingsCopy(chat_id)].endpoint(settings_copy_command_handler))
+        .branch(case![Command::Lockdown].endpoint(lockdown_command_handler))
+        .branch(case![Command::Unlock].endpoint(unlock_command_handler))
         .branch(case![Command::Cancel].endpoint(cancel_handler));
 
     let message_handler = Update::filter_message()
         .branch(Message::filter_new_chat_members().endpoint(new_chat_members_handler))
         .branch(Message::filter_left_chat_member().endpoint(left_chat_member_handler))
         .branch(command_handler)
-        .branch(case![SettingsState::Settings { user_id }].endpoint(input_settings_handler))
+        .branch(
+            dptree::filter_map(find_blocked_content)
+                .filter_async(
+                    |bot: Bot, upd: Update| async move { !is_user_privileged(bot, upd).await },
+                )
+                .endpoint(blocked_message_handler),
+        )
+        .branch(dptree::filter_async(is_newcomer_message).endpoint(newcomer_message_handler))
+        .branch(
+            case![SettingsState::Setting {
+                user_id,
+                key,
+                menu_id
+            }]
+            .endpoint(input_setting_handler),
+        )
         .branch(case![SettingsState::Greeting { user_id }].endpoint(input_greeting_handler))
+        .branch(case![SettingsState::Question { user_id }].endpoint(input_question_handler))
         .filter(is_channel_message)
         .endpoint(channel_message_handler);
 
-    dialogue::enter::<Update, InMemStorage<SettingsState>, SettingsState, _>()
-        .filter(is_group_or_supergroup)
-        .branch(message_handler)
-        .branch(Update::filter_callback_query().endpoint(captcha_response_handler))
+    let group_handler =
+        dialogue::enter::<Update, DialogueStorage<SettingsState>, SettingsState, _>()
+            .filter(is_group_or_supergroup)
+            .branch(message_handler)
+            .branch(Update::filter_chat_member().endpoint(chat_member_handler))
+            .branch(Update::filter_chat_join_request().endpoint(join_request_handler))
+            .branch(
+                Update::filter_callback_query()
+                    .filter_map(parse_settings_query)
+                    .filter_async(is_user_privileged)
+                    .endpoint(settings_query_handler),
+            )
+            .branch(Update::filter_callback_query().endpoint(captcha_response_handler));
+
+    let private_handler = dptree::filter(is_private_chat)
+        .branch(
+            Update::filter_message()
+                .filter_map(parse_start_link)
+                .endpoint(start_handler),
+        )
+        .branch(Update::filter_callback_query().endpoint(captcha_response_handler));
+
+    // Messages with stories can't be parsed by teloxide, they come as raw updates.
+    let story_handler = dptree::filter_map(parse_story_message).endpoint(story_message_handler);
+
+    dptree::entry()
+        .branch(story_handler)
+        .branch(private_handler)
+        .branch(group_handler)
 }
 
 fn is_channel_message(upd: Update) -> bool {
@@ -156,6 +327,10 @@ fn is_channel_message(upd: Update) -> bool {
     }
 }
 
+fn is_private_chat(upd: Update) -> bool {
+    upd.chat().map(|chat| chat.is_private()).unwrap_or(false)
+}
+
 fn is_group_or_supergroup(upd: Update) -> bool {
     if let Some(chat) = upd.chat() {
         chat.is_group() || chat.is_supergroup()
@@ -175,8 +350,161 @@ async fn is_user_privileged(bot: Bot, upd: Update) -> bool {
     false
 }
 
+/// Finds content blocked by the chat filters, messages sent on behalf of chats are left to `ban_channels`.
+fn find_blocked_content(msg: Message) -> Option<spam::Reason> {
+    if msg.sender_chat().is_some() {
+        return None;
+    }
+
+    let settings = settings::get(msg.chat.id);
+    if settings.block_invites && spam::has_invite_link(&msg) {
+        Some(spam::Reason::InviteLink)
+    } else if settings.block_urls && spam::has_link(&msg) {
+        Some(spam::Reason::Link)
+    } else if settings.block_channel_forwards && spam::is_channel_forward(&msg) {
+        Some(spam::Reason::ChannelForward)
+    } else if settings.block_contacts && msg.contact().is_some() {
+        Some(spam::Reason::Contact)
+    } else {
+        None
+    }
+}
+
+async fn blocked_message_handler(bot: Bot, msg: Message, reason: spam::Reason) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let Some(user) = msg.from() else {
+        return Ok(());
+    };
+    log::info!("Deleting {reason} posted by {} in chat {chat_id}", user.id);
+    bot.delete_message(chat_id, msg.id).await?;
+
+    let settings = settings::get(chat_id);
+    let key = match reason {
+        spam::Reason::InviteLink => "filter-invite-deleted",
+        spam::Reason::ChannelForward => "filter-channel-forward-deleted",
+        spam::Reason::Contact => "filter-contact-deleted",
+        _ => "filter-url-deleted",
+    };
+    let user_tag = html::user_mention_or_link(user);
+    send_filter_notice(&bot, chat_id, key, user_tag, &settings).await
+}
+
+/// Message with a story posted by a user.
+#[derive(Clone)]
+struct StoryMessage {
+    chat_id: ChatId,
+    message_id: MessageId,
+    user_id: UserId,
+    first_name: String,
+}
+
+/// Picks messages with stories from raw updates of chats which block stories.
+fn parse_story_message(upd: Update) -> Option<StoryMessage> {
+    let UpdateKind::Error(value) = upd.kind else {
+        return None;
+    };
+    let message = value.get("message")?;
+    message.get("story")?;
+    if message.get("sender_chat").is_some() {
+        return None;
+    }
+
+    let chat_id = ChatId(message.get("chat")?.get("id")?.as_i64()?);
+    if chat_id.is_user() || !settings::get(chat_id).block_stories {
+        return None;
+    }
+    let from = message.get("from")?;
+    Some(StoryMessage {
+        chat_id,
+        message_id: MessageId(message.get("message_id")?.as_i64()? as i32),
+        user_id: UserId(from.get("id")?.as_u64()?),
+        first_name: from.get("first_name")?.as_str()?.to_owned(),
+    })
+}
+
+async fn story_message_handler(bot: Bot, story: StoryMessage) -> HandlerResult {
+    let chat_id = story.chat_id;
+    let member = bot.get_chat_member(chat_id, story.user_id).await?;
+    if member.kind.is_privileged() {
+        return Ok(());
+    }
+    log::info!(
+        "Deleting story posted by {} in chat {chat_id}",
+        story.user_id
+    );
+    bot.delete_message(chat_id, story.message_id).await?;
+
+    let settings = settings::get(chat_id);
+    let user_tag = html::user_mention(story.user_id.0 as i64, &story.first_name);
+    send_filter_notice(&bot, chat_id, "filter-story-deleted", user_tag, &settings).await
+}
+
+async fn send_filter_notice(
+    bot: &Bot,
+    chat_id: ChatId,
+    key: &str,
+    user_tag: String,
+    settings: &Settings,
+) -> HandlerResult {
+    let text = t!(key, settings.language, user_tag = user_tag);
+    let message = bot.send_message(chat_id, text).await?;
+    utils::delete_message_later(bot, chat_id, message.id, settings.message_expire());
+
+    Ok(())
+}
+
+/// Whether the message is one of the first messages of a user who passed CAPTCHA.
+async fn is_newcomer_message(msg: Message) -> bool {
+    let Some(user) = msg.from() else {
+        return false;
+    };
+    match redis::count_probation_message(msg.chat.id, user.id).await {
+        Ok(counted) => counted,
+        Err(error) => {
+            log::error!("Failed to count message of newcomer {}: {error}", user.id);
+            false
+        }
+    }
+}
+
+async fn newcomer_message_handler(bot: Bot, msg: Message) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let settings = settings::get(chat_id);
+    let (Some(user), Some(reason)) = (msg.from(), spam::check(&msg)) else {
+        return Ok(());
+    };
+    if settings.newcomer_policy == NewcomerPolicy::Off {
+        return Ok(());
+    }
+
+    log::info!("Newcomer {} posted {reason} in chat {chat_id}", user.id);
+    bot.delete_message(chat_id, msg.id).await?;
+    let fail_action = match settings.newcomer_policy {
+        NewcomerPolicy::Kick => Some(FailAction::Kick),
+        NewcomerPolicy::Ban => Some(FailAction::Ban),
+        NewcomerPolicy::Off | NewcomerPolicy::Delete => None,
+    };
+    if let Some(fail_action) = fail_action {
+        redis::end_probation(chat_id, user.id).await?;
+        utils::apply_fail_action(&bot, chat_id, user.id, fail_action).await;
+    }
+
+    let text = t!(
+        "newcomer-message-deleted",
+        settings.language,
+        user_tag = html::user_mention_or_link(user)
+    );
+    let message = bot.send_message(chat_id, text).await?;
+    utils::delete_message_later(&bot, chat_id, message.id, settings.message_expire());
+
+    Ok(())
+}
+
 async fn left_chat_member_handler(bot: Bot, msg: Message) -> HandlerResult {
     let chat_id = msg.chat.id;
+    if let Some(user) = msg.left_chat_member() {
+        redis::unmark_joined(chat_id, user.id).await?;
+    }
     let settings = settings::get(chat_id);
     if settings.delete_entry_messages {
         bot.delete_message(chat_id, msg.id).await?;
@@ -213,76 +541,264 @@ async fn channel_message_handler(bot: Bot, msg: Message) -> HandlerResult {
     Ok(())
 }
 
-async fn input_settings_handler(
+async fn input_setting_handler(
     bot: Bot,
     msg: Message,
     dialogue: SettingsDialogue,
-    user_id: UserId,
+    (user_id, key, menu_id): (UserId, String, MessageId),
 ) -> HandlerResult {
     if user_id != msg.from().unwrap().id {
         return Ok(());
     }
 
     let chat_id = msg.chat.id;
-    let text = msg.text();
-    let mut settings = settings::get(chat_id);
-
-    if text.is_none() {
+    let settings = settings::get(chat_id);
+    let Some(text) = msg.text() else {
         let text = t!("settings-text-required", settings.language);
         bot.send_message(chat_id, text).await?;
         return Ok(());
+    };
+
+    let changed = match RawSetting::parse(&key, text) {
+        Ok(raw_setting) => change_setting(&bot, chat_id, raw_setting).await?,
+        Err(error) => Err(error),
+    };
+    let settings = match changed {
+        Ok(settings) => settings,
+        Err(error) => {
+            let text = t!(
+                "settings-invalid-value",
+                settings.language,
+                error = html::escape(&error.localize(settings.language))
+            );
+            bot.send_message(chat_id, text).await?;
+            return Ok(());
+        }
+    };
+
+    let text = t!("settings-changed", settings.language);
+    let message = bot.send_message(chat_id, text).await?;
+    utils::delete_message_later(&bot, chat_id, message.id, settings.message_expire());
+    dialogue.exit().await?;
+
+    // The menu may have been closed meanwhile.
+    let _ = bot
+        .edit_message_text(chat_id, menu_id, t!("settings-menu", settings.language))
+        .reply_markup(menu::main_keyboard(&settings))
+        .disable_web_page_preview(true)
+        .await;
+
+    Ok(())
+}
+
+/// Applies the setting and saves settings of the chat unless they conflict with each other.
+async fn change_setting(
+    bot: &Bot,
+    chat_id: ChatId,
+    raw_setting: RawSetting,
+) -> Result<Result<Settings, RawSettingError>, anyhow::Error> {
+    let mut settings = settings::get(chat_id);
+    apply_setting(bot, chat_id, &mut settings, raw_setting).await?;
+
+    if let Err(error) = settings.validate() {
+        return Ok(Err(error));
     }
+    settings::set(chat_id, settings.clone()).await?;
 
-    let raw_settings = RawSetting::from_str(text.unwrap());
+    Ok(Ok(settings))
+}
 
-    if let Err(error) = raw_settings {
-        bot.send_message(
-            chat_id,
-            format!("Parsing error: {error}.\nTry again or /cancel"),
-        )
-        .await?;
-        return Ok(());
+/// `ban_channels` needs the linked channel of the chat, so it's requested from Telegram.
+async fn apply_setting(
+    bot: &Bot,
+    chat_id: ChatId,
+    settings: &mut Settings,
+    raw_setting: RawSetting,
+) -> HandlerResult {
+    match raw_setting {
+        RawSetting::Language(lang) => settings.language = lang,
+        RawSetting::BanChannels(val) => {
+            let val = if val {
+                let chat = bot.get_chat(chat_id).await?;
+
+                if let ChatKind::Public(ChatPublic {
+                    kind:
+                        PublicChatKind::Supergroup(PublicChatSupergroup {
+                            linked_chat_id: Some(linked_chat_id),
+                            ..
+                        }),
+                    ..
+                }) = chat.kind
+                {
+                    Some(BanChannels::AllExceptLinked(linked_chat_id))
+                } else {
+                    Some(BanChannels::All)
+                }
+            } else {
+                None
+            };
+            settings.ban_channels = val;
+        }
+        RawSetting::CaptchaExpire(val) => settings.captcha_expire = val,
+        RawSetting::MessageExpire(val) => settings.message_expire = val,
+        RawSetting::IgnoreExpire(val) => settings.ignore_expire = val,
+        RawSetting::DeleteEntryMessages(val) => settings.delete_entry_messages = val,
+        RawSetting::CaptchaOptions(val) => settings.captcha_options = val,
+        RawSetting::CaptchaRows(val) => settings.captcha_rows = val,
+        RawSetting::CaptchaAttempts(val) => settings.captcha_attempts = val,
+        RawSetting::FailAction(val) => settings.fail_action = val,
+        RawSetting::ApproveJoinRequests(val) => settings.approve_join_requests = val,
+        RawSetting::CaptchaLocation(val) => settings.captcha_location = val,
+        RawSetting::CaptchaType(val) => settings.captcha_type = val,
+        RawSetting::RaidThreshold(val) => settings.raid_threshold = val,
+        RawSetting::LockdownThreshold(val) => settings.lockdown_threshold = val,
+        RawSetting::LockdownDuration(val) => settings.lockdown_duration = val,
+        RawSetting::NewcomerPolicy(val) => settings.newcomer_policy = val,
+        RawSetting::NewcomerMessages(val) => settings.newcomer_messages = val,
+        RawSetting::BlockUrls(val) => settings.block_urls = val,
+        RawSetting::BlockInvites(val) => settings.block_invites = val,
+        RawSetting::BlockChannelForwards(val) => settings.block_channel_forwards = val,
+        RawSetting::BlockStories(val) => settings.block_stories = val,
+        RawSetting::BlockContacts(val) => settings.block_contacts = val,
     }
 
-    for raw_setting in raw_settings.unwrap() {
-        match raw_setting {
-            RawSetting::Language(lang) => settings.language = lang,
-            RawSetting::BanChannels(val) => {
-                let val = if val {
-                    let chat = bot.get_chat(chat_id).await?;
-
-                    if let ChatKind::Public(ChatPublic {
-                        kind:
-                            PublicChatKind::Supergroup(PublicChatSupergroup {
-                                linked_chat_id: Some(linked_chat_id),
-                                ..
-                            }),
-                        ..
-                    }) = chat.kind
-                    {
-                        Some(BanChannels::AllExceptLinked(linked_chat_id))
-                    } else {
-                        Some(BanChannels::All)
+    Ok(())
+}
+
+fn parse_settings_query(query: CallbackQuery) -> Option<menu::Action> {
+    query.message.as_ref()?;
+    query.data.as_deref().and_then(menu::Action::parse)
+}
+
+async fn settings_query_handler(
+    bot: Bot,
+    query: CallbackQuery,
+    action: menu::Action,
+    dialogue: SettingsDialogue,
+) -> HandlerResult {
+    // `message` is `Some(_)` because of `parse_settings_query` filter in `schema()`.
+    let message = query.message.unwrap();
+    let chat_id = message.chat.id;
+    let settings = settings::get(chat_id);
+
+    // Buttons of a menu shown by an older version of the bot may refer to unknown settings.
+    let key = match &action {
+        menu::Action::Open(key) | menu::Action::Set(key, _) | menu::Action::Input(key) => Some(key),
+        menu::Action::Main | menu::Action::Close => None,
+    };
+    if key.is_some_and(|key| RawSetting::choice(key).is_none()) {
+        return answer_outdated(&bot, query.id, &settings).await;
+    }
+
+    match action {
+        menu::Action::Main => match show_settings_menu(&bot, &message, &settings).await {
+            Err(error) if is_outdated(&error) => answer_outdated(&bot, query.id, &settings).await?,
+            result => {
+                result?;
+                bot.answer_callback_query(query.id).await?;
+            }
+        },
+        menu::Action::Open(key) => {
+            if let Some(keyboard) = menu::options_keyboard(&settings, &key) {
+                let value = RawSetting::display(&settings, &key).unwrap_or_default();
+                let text = t!(
+                    "settings-choose-value",
+                    settings.language,
+                    key = key,
+                    value = value
+                );
+                let edited = bot
+                    .edit_message_text(chat_id, message.id, text)
+                    .reply_markup(keyboard)
+                    .await;
+                match edited {
+                    Err(error) if is_outdated(&error) => {
+                        return answer_outdated(&bot, query.id, &settings).await;
                     }
-                } else {
-                    None
+                    result => result?,
                 };
-                settings.ban_channels = val;
             }
-            RawSetting::CaptchaExpire(val) => settings.captcha_expire = val,
-            RawSetting::MessageExpire(val) => settings.message_expire = val,
-            RawSetting::IgnoreExpire(val) => settings.ignore_expire = val,
-            RawSetting::DeleteEntryMessages(val) => settings.delete_entry_messages = val,
+            bot.answer_callback_query(query.id).await?;
+        }
+        menu::Action::Set(key, value) => {
+            let changed = match RawSetting::parse(&key, &value) {
+                Ok(raw_setting) => change_setting(&bot, chat_id, raw_setting).await?,
+                Err(error) => Err(error),
+            };
+            match changed {
+                Ok(settings) => {
+                    let text = t!("settings-changed", settings.language);
+                    bot.answer_callback_query(query.id).text(text).await?;
+                    show_settings_menu(&bot, &message, &settings).await?;
+                }
+                Err(error) => {
+                    let text = t!(
+                        "settings-invalid-value",
+                        settings.language,
+                        error = error.localize(settings.language)
+                    );
+                    bot.answer_callback_query(query.id)
+                        .text(text)
+                        .show_alert(true)
+                        .await?;
+                }
+            }
+        }
+        menu::Action::Input(key) => {
+            bot.answer_callback_query(query.id).await?;
+            dialogue
+                .update(SettingsState::Setting {
+                    user_id: query.from.id,
+                    key: key.clone(),
+                    menu_id: message.id,
+                })
+                .await?;
+            let user_tag = html::user_mention_or_link(&query.from);
+            let text = t!(
+                "settings-input-value",
+                settings.language,
+                user_tag = user_tag,
+                key = key
+            );
+            bot.send_message(chat_id, text).await?;
+        }
+        menu::Action::Close => {
+            bot.answer_callback_query(query.id).await?;
+            bot.delete_message(chat_id, message.id).await?;
         }
     }
 
-    let lang = settings.language;
-    let message_expire = settings.message_expire();
-    settings::set(chat_id, settings).await?;
-    let text = t!("settings-changed", lang);
-    let message = bot.send_message(chat_id, text).await?;
-    let _ = utils::delete_message_later(&bot, chat_id, message.id, message_expire);
-    dialogue.exit().await?;
+    Ok(())
+}
+
+async fn show_settings_menu(
+    bot: &Bot,
+    message: &Message,
+    settings: &Settings,
+) -> Result<(), RequestError> {
+    let text = t!("settings-menu", settings.language);
+    bot.edit_message_text(message.chat.id, message.id, text)
+        .reply_markup(menu::main_keyboard(settings))
+        .disable_web_page_preview(true)
+        .await?;
+
+    Ok(())
+}
+
+/// Whether the menu message is too old to be edited or was deleted.
+fn is_outdated(error: &RequestError) -> bool {
+    matches!(
+        error,
+        RequestError::Api(ApiError::MessageCantBeEdited | ApiError::MessageToEditNotFound)
+    )
+}
+
+async fn answer_outdated(bot: &Bot, query_id: String, settings: &Settings) -> HandlerResult {
+    let text = t!("settings-message-outdated", settings.language);
+    bot.answer_callback_query(query_id)
+        .text(text)
+        .show_alert(true)
+        .await?;
 
     Ok(())
 }
@@ -311,28 +827,16 @@ async fn greeting_command_handler(
     Ok(())
 }
 
-async fn settings_command_handler(
-    bot: Bot,
-    msg: Message,
-    dialogue: SettingsDialogue,
-) -> HandlerResult {
+async fn settings_command_handler(bot: Bot, msg: Message) -> HandlerResult {
     let chat_id = msg.chat.id;
     let settings = settings::get(chat_id);
-    let text = RawSetting::to_string(&settings);
+    let text = t!("settings-menu", settings.language);
 
     bot.send_message(chat_id, text)
         .reply_to_message_id(msg.id)
-        .await?;
-
-    let text = t!("settings-input", settings.language);
-    bot.send_message(chat_id, text)
+        .reply_markup(menu::main_keyboard(&settings))
         .disable_web_page_preview(true)
         .await?;
-    dialogue
-        .update(SettingsState::Settings {
-            user_id: msg.from().unwrap().id,
-        })
-        .await?;
 
     Ok(())
 }
@@ -344,14 +848,18 @@ async fn cancel_handler(bot: Bot, msg: Message, dialogue: SettingsDialogue) -> H
         return Ok(());
     }
 
-    if let Some(SettingsState::Settings { user_id } | SettingsState::Greeting { user_id }) = state {
+    if let Some(
+        SettingsState::Setting { user_id, .. }
+        | SettingsState::Greeting { user_id }
+        | SettingsState::Question { user_id },
+    ) = state
+    {
         if user_id == msg.from().unwrap().id {
             let chat_id = msg.chat.id;
             let settings = settings::get(chat_id);
             let text = t!("settings-cancel", settings.language);
             let message = bot.send_message(chat_id, text).await?;
-            let _ =
-                utils::delete_message_later(&bot, chat_id, message.id, settings.message_expire());
+            utils::delete_message_later(&bot, chat_id, message.id, settings.message_expire());
 
             dialogue.exit().await?;
         }
@@ -399,8 +907,7 @@ async fn input_greeting_handler(
     match bot.send_message(chat_id, text).await {
         Ok(message) => {
             settings::set_greeting(chat_id, greeting).await?;
-            let _ =
-                utils::delete_message_later(&bot, chat_id, message.id, settings.message_expire());
+            utils::delete_message_later(&bot, chat_id, message.id, settings.message_expire());
             dialogue.exit().await?;
         }
         Err(error) => {
@@ -411,107 +918,739 @@ async fn input_greeting_handler(
     Ok(())
 }
 
-async fn help_command_handler(bot: Bot, msg: Message) -> HandlerResult {
-    bot.send_message(msg.chat.id, Command::descriptions().to_string())
+async fn question_command_handler(
+    bot: Bot,
+    msg: Message,
+    dialogue: SettingsDialogue,
+) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let text = t!("settings-input-question", settings::lang(chat_id));
+
+    bot.send_message(chat_id, text)
         .reply_to_message_id(msg.id)
         .await?;
+    dialogue
+        .update(SettingsState::Question {
+            user_id: msg.from().unwrap().id,
+        })
+        .await?;
+
     Ok(())
 }
 
-async fn new_chat_members_handler(
+async fn input_question_handler(
     bot: Bot,
     msg: Message,
-    chat_members: Vec<User>,
-    me: Me,
+    dialogue: SettingsDialogue,
+    user_id: UserId,
 ) -> HandlerResult {
-    let chat_id = msg.chat.id;
-    let users = chat_members.iter().filter(|m| !m.is_bot);
-
-    // Restrict new users as soon as possible.
-    let users_futures = users.clone().map(|user| {
-        bot.restrict_chat_member(chat_id, user.id, ChatPermissions::empty())
-            .into_future()
-    });
-    let restrictions = futures::future::join_all(users_futures).await;
+    if user_id != msg.from().unwrap().id {
+        return Ok(());
+    }
 
+    let chat_id = msg.chat.id;
     let settings = settings::get(chat_id);
-    if settings.delete_entry_messages {
-        tokio::spawn(bot.delete_message(chat_id, msg.id).into_future());
-    }
 
-    // Show error from the first failed restriction (if any).
-    let failed_restrictions: Vec<_> = restrictions.iter().filter(|r| r.is_err()).collect();
-    if failed_restrictions.len() > 0 {
-        if let Err(error) = failed_restrictions[0] {
-            let message = format!("Failed to restrict user: {error}");
-            log::error!("{message}");
-            bot.send_message(chat_id, message).await?;
-        }
-    }
+    // Either a picture with emoji in the caption or "emoji: phrase" text.
+    let raw_question = if let Some(photo) = msg.photo().and_then(|sizes| sizes.last()) {
+        RawQuestion::parse_emoji(msg.caption().unwrap_or_default())
+            .map(|emoji| (emoji, Picture::File(photo.file.id.clone())))
+    } else if let Some(text) = msg.text() {
+        text.parse::<RawQuestion>()
+            .map(|question| (question.emoji, Picture::Phrase(question.phrase)))
+    } else {
+        let text = t!("settings-text-required", settings.language);
+        bot.send_message(chat_id, text).await?;
+        return Ok(());
+    };
 
-    let bots = chat_members.iter().filter(|m| m.is_bot);
-    for b in bots {
-        if b.id == me.id {
-            let text = t!("make-me-admin", settings.language);
+    let (emoji, picture) = match raw_question {
+        Ok(question) => question,
+        Err(error) => {
+            log::info!("Invalid question: {error}");
+            let text = t!("settings-input-question", settings.language);
             bot.send_message(chat_id, text).await?;
+            return Ok(());
         }
-        log::info!(
-            "New member {} is a bot, skipping captcha",
-            b.username.as_ref().unwrap()
-        );
+    };
+
+    if settings::is_emoji_used(chat_id, &emoji) {
+        let text = t!("settings-question-exists", settings.language, emoji = emoji);
+        bot.send_message(chat_id, text).await?;
+        return Ok(());
     }
 
-    let restricted_users = users.enumerate().filter(|(i, _)| restrictions[*i].is_ok());
-    for (_, user) in restricted_users {
-        // Skip showing CAPTCHA for ignored users.
-        if redis::is_ignored(chat_id, user.id).await {
-            log::info!("Ignoring user {} in chat {}", user.id, chat_id);
-            continue;
-        }
+    settings::add_question(chat_id, emoji.clone(), picture).await?;
+    let text = t!("settings-question-added", settings.language, emoji = emoji);
+    let message = bot.send_message(chat_id, text).await?;
+    utils::delete_message_later(&bot, chat_id, message.id, settings.message_expire());
+    dialogue.exit().await?;
 
-        let comb = qna::Combination::pick(6);
-        log::info!("{comb}");
+    Ok(())
+}
 
-        // TODO: Handle request errors from Qwant.com
-        let user_tag = html::user_mention_or_link(user);
-        let url = qwant::get_image_url(comb.query_phrase).await?;
-        log::info!("Image URL: {url}");
-        let keyboard = utils::emojis_keyboard(&comb.emojis, 2);
-        let caption = t!(
-            "captcha-caption",
-            settings.language,
-            user_tag = &user_tag,
-            duration = settings.captcha_expire.get()
-        );
-        let message = bot
-            .send_photo(chat_id, InputFile::url(url))
-            .caption(caption)
-            .reply_markup(keyboard)
-            .await?;
-        let text = t!(
-            "captcha-time-over",
-            settings.language,
-            user_tag = user_tag,
-            duration = settings.ignore_expire.get()
-        );
-        let _ = utils::delete_captcha_later(
-            &bot,
-            chat_id,
-            message.id,
-            text,
-            settings.captcha_expire(),
-            settings.message_expire(),
-        );
-        redis::set_answer(
+async fn questions_command_handler(bot: Bot, msg: Message) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let lang = settings::lang(chat_id);
+    let questions = settings::get_questions(chat_id);
+
+    let text = if questions.is_empty() {
+        t!("settings-questions-empty", lang)
+    } else {
+        questions
+            .iter()
+            .map(|question| {
+                let picture = match &question.picture {
+                    Picture::Phrase(phrase) => html::escape(phrase),
+                    Picture::File(_) => t!("settings-question-picture", lang),
+                };
+                format!(
+                    "{}. {} — {picture}",
+                    question.id,
+                    html::escape(&question.emoji)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    bot.send_message(chat_id, text)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+async fn delete_question_command_handler(bot: Bot, msg: Message, id: i64) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let settings = settings::get(chat_id);
+
+    let text = if settings::delete_question(chat_id, id).await? {
+        t!("settings-question-deleted", settings.language)
+    } else {
+        t!("settings-question-not-found", settings.language)
+    };
+    let message = bot.send_message(chat_id, text).await?;
+    utils::delete_message_later(&bot, chat_id, message.id, settings.message_expire());
+
+    Ok(())
+}
+
+async fn get_command_handler(bot: Bot, msg: Message, key: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let settings = settings::get(chat_id);
+    let key = key.trim();
+
+    let text = if key.is_empty() {
+        settings::KEYS
+            .iter()
+            .filter_map(|key| {
+                let value = RawSetting::display(&settings, key)?;
+                Some(format!("{key}: <code>{}</code>", html::escape(&value)))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else if let Some(value) = RawSetting::display(&settings, key) {
+        format!("{key}: <code>{}</code>", html::escape(&value))
+    } else {
+        let error = RawSettingError::UnknownSetting(key.to_owned());
+        t!(
+            "settings-invalid-value",
+            settings.language,
+            error = html::escape(&error.localize(settings.language))
+        )
+    };
+    bot.send_message(chat_id, text)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+async fn set_command_handler(bot: Bot, msg: Message, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let Some((key, value)) = args.trim().split_once(char::is_whitespace) else {
+        let settings = settings::get(chat_id);
+        let text = t!("settings-set-usage", settings.language);
+        bot.send_message(chat_id, text)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    };
+
+    let raw_setting = RawSetting::parse(key, value.trim());
+    reply_setting_changed(&bot, &msg, raw_setting).await
+}
+
+async fn reset_command_handler(bot: Bot, msg: Message, key: String) -> HandlerResult {
+    let key = key.trim();
+    let raw_setting = match RawSetting::value(&Settings::default(), key) {
+        Some(value) => RawSetting::parse(key, &value),
+        None => Err(RawSettingError::UnknownSetting(key.to_owned())),
+    };
+    reply_setting_changed(&bot, &msg, raw_setting).await
+}
+
+/// Changes a setting given in a command and tells whether it succeeded.
+async fn reply_setting_changed(
+    bot: &Bot,
+    msg: &Message,
+    raw_setting: Result<RawSetting, RawSettingError>,
+) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let changed = match raw_setting {
+        Ok(raw_setting) => change_setting(bot, chat_id, raw_setting).await?,
+        Err(error) => Err(error),
+    };
+    let text = match changed {
+        Ok(settings) => t!("settings-changed", settings.language),
+        Err(error) => {
+            let settings = settings::get(chat_id);
+            t!(
+                "settings-invalid-value",
+                settings.language,
+                error = html::escape(&error.localize(settings.language))
+            )
+        }
+    };
+    bot.send_message(chat_id, text)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+async fn settings_export_command_handler(bot: Bot, msg: Message) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let json = serde_json::to_string_pretty(&export::export(chat_id))?;
+    let file = InputFile::memory(json.into_bytes()).file_name(format!("settings{chat_id}.json"));
+    bot.send_document(chat_id, file)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+async fn settings_import_command_handler(bot: Bot, msg: Message, json: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let reply = msg.reply_to_message();
+
+    let json = if !json.trim().is_empty() {
+        json
+    } else if let Some(document) = reply.and_then(|reply| reply.document()) {
+        if document.file.size > MAX_IMPORT_SIZE {
+            let error = t!(
+                "settings-error-file-too-large",
+                settings::lang(chat_id),
+                size = MAX_IMPORT_SIZE
+            );
+            return reply_import_failed(&bot, &msg, error).await;
+        }
+        let file = bot.get_file(&document.file.id).await?;
+        let mut data = Vec::new();
+        bot.download_file(&file.path, &mut data).await?;
+        match String::from_utf8(data) {
+            Ok(json) => json,
+            Err(_) => {
+                let error = t!("settings-error-not-utf8", settings::lang(chat_id));
+                return reply_import_failed(&bot, &msg, error).await;
+            }
+        }
+    } else if let Some(text) = reply.and_then(|reply| reply.text()) {
+        text.to_owned()
+    } else {
+        let settings = settings::get(chat_id);
+        let text = t!("settings-import-usage", settings.language);
+        bot.send_message(chat_id, text)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    };
+
+    import_settings(&bot, &msg, export::parse(&json)).await
+}
+
+async fn settings_copy_command_handler(bot: Bot, msg: Message, source: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let settings = settings::get(chat_id);
+    let Ok(source_id) = source.trim().parse().map(ChatId) else {
+        let text = t!("settings-copy-usage", settings.language);
+        bot.send_message(chat_id, text)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    };
+
+    // Being admin of this chat is checked in `schema()`, the source chat is checked here.
+    let user_id = msg.from().unwrap().id;
+    let is_source_admin = bot
+        .get_chat_member(source_id, user_id)
+        .await
+        .map(|member| member.kind.is_privileged())
+        .unwrap_or(false);
+    if !is_source_admin {
+        let text = t!("settings-copy-not-admin", settings.language);
+        bot.send_message(chat_id, text)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    import_settings(&bot, &msg, export::check(export::export(source_id))).await
+}
+
+/// Applies all imported settings with greeting and questions at once, or none of them.
+async fn import_settings(
+    bot: &Bot,
+    msg: &Message,
+    import: Result<export::Import, export::ImportError>,
+) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let import = match import {
+        Ok(import) => import,
+        Err(error) => {
+            let error = error.localize(settings::lang(chat_id));
+            return reply_import_failed(bot, msg, error).await;
+        }
+    };
+
+    let mut settings = settings::get(chat_id);
+    for raw_setting in import.settings {
+        apply_setting(bot, chat_id, &mut settings, raw_setting).await?;
+    }
+    if let Err(error) = settings.validate() {
+        let error = error.localize(settings::lang(chat_id));
+        return reply_import_failed(bot, msg, error).await;
+    }
+
+    let lang = settings.language;
+    settings::replace(chat_id, settings, import.greeting, import.questions).await?;
+    let text = t!("settings-imported", lang);
+    bot.send_message(chat_id, text)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+async fn reply_import_failed(bot: &Bot, msg: &Message, error: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let settings = settings::get(chat_id);
+    let text = t!(
+        "settings-import-failed",
+        settings.language,
+        error = html::escape(&error)
+    );
+    bot.send_message(chat_id, text)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+async fn lockdown_command_handler(bot: Bot, msg: Message) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let settings = settings::get(chat_id);
+
+    if !lockdown::start(&bot, chat_id, settings.lockdown_duration()).await? {
+        let text = t!("lockdown-already-started", settings.language);
+        let message = bot.send_message(chat_id, text).await?;
+        utils::delete_message_later(&bot, chat_id, message.id, settings.message_expire());
+    }
+
+    Ok(())
+}
+
+async fn unlock_command_handler(bot: Bot, msg: Message) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let settings = settings::get(chat_id);
+
+    if !lockdown::lift(&bot, chat_id).await? {
+        let text = t!("lockdown-not-started", settings.language);
+        let message = bot.send_message(chat_id, text).await?;
+        utils::delete_message_later(&bot, chat_id, message.id, settings.message_expire());
+    }
+
+    Ok(())
+}
+
+async fn help_command_handler(bot: Bot, msg: Message) -> HandlerResult {
+    bot.send_message(msg.chat.id, Command::descriptions().to_string())
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+async fn new_chat_members_handler(
+    bot: Bot,
+    msg: Message,
+    chat_members: Vec<User>,
+    me: Me,
+) -> HandlerResult {
+    let chat_id = msg.chat.id;
+
+    // Skip users already handled by `chat_member_handler`.
+    let mut users = vec![];
+    for user in chat_members.iter().filter(|m| !m.is_bot) {
+        if is_new_join(chat_id, user.id).await {
+            users.push(user);
+        }
+    }
+
+    // Restrict new users as soon as possible.
+    let users_futures = users.iter().map(|user| {
+        bot.restrict_chat_member(chat_id, user.id, ChatPermissions::empty())
+            .into_future()
+    });
+    let restrictions = futures::future::join_all(users_futures).await;
+
+    let settings = settings::get(chat_id);
+    if settings.delete_entry_messages {
+        tokio::spawn(bot.delete_message(chat_id, msg.id).into_future());
+    }
+
+    // Show error from the first failed restriction (if any).
+    let failed_restrictions: Vec<_> = restrictions.iter().filter(|r| r.is_err()).collect();
+    if !failed_restrictions.is_empty() {
+        if let Err(error) = failed_restrictions[0] {
+            let message = format!("Failed to restrict user: {error}");
+            log::error!("{message}");
+            bot.send_message(chat_id, message).await?;
+        }
+    }
+
+    let bots = chat_members.iter().filter(|m| m.is_bot);
+    for b in bots {
+        if b.id == me.id {
+            let text = t!("make-me-admin", settings.language);
+            bot.send_message(chat_id, text).await?;
+        }
+        log::info!(
+            "New member {} is a bot, skipping captcha",
+            b.username.as_ref().unwrap()
+        );
+    }
+
+    let restricted_users = users
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| restrictions[*i].is_ok());
+    // One failed CAPTCHA shouldn't stop others from being shown.
+    for (_, user) in restricted_users {
+        if let Err(error) = send_captcha(&bot, &me, chat_id, user, &settings).await {
+            log::error!(
+                "Failed to show CAPTCHA to user {} in {chat_id}: {error}",
+                user.id
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Handles users joining without "User joined the group" message,
+/// e.g. when the message is hidden or a join request was approved.
+async fn chat_member_handler(bot: Bot, upd: ChatMemberUpdated, me: Me) -> HandlerResult {
+    let joined = !upd.old_chat_member.is_present() && upd.new_chat_member.is_present();
+    let left = upd.old_chat_member.is_present() && !upd.new_chat_member.is_present();
+    let user = &upd.new_chat_member.user;
+
+    // User left or was kicked or banned, rejoining must show CAPTCHA again.
+    if left {
+        redis::unmark_joined(upd.chat.id, user.id).await?;
+        return Ok(());
+    }
+
+    if !joined || user.is_bot {
+        return Ok(());
+    }
+
+    let chat_id = upd.chat.id;
+    // Skip users already handled by `new_chat_members_handler`.
+    if !is_new_join(chat_id, user.id).await {
+        return Ok(());
+    }
+
+    let restriction = bot
+        .restrict_chat_member(chat_id, user.id, ChatPermissions::empty())
+        .await;
+    if let Err(error) = restriction {
+        let message = format!("Failed to restrict user: {error}");
+        log::error!("{message}");
+        bot.send_message(chat_id, message).await?;
+        return Ok(());
+    }
+
+    let settings = settings::get(chat_id);
+    send_captcha(&bot, &me, chat_id, user, &settings).await
+}
+
+/// Marks the user as joined, returns `false` if the join was already handled.
+/// Users are considered new if Redis fails: a duplicate CAPTCHA is better than no restriction.
+async fn is_new_join(chat_id: ChatId, user_id: UserId) -> bool {
+    match redis::mark_joined(chat_id, user_id).await {
+        Ok(new) => new,
+        Err(error) => {
+            log::error!("Failed to mark user {user_id} joined chat {chat_id}: {error}");
+            true
+        }
+    }
+}
+
+async fn send_captcha(
+    bot: &Bot,
+    me: &Me,
+    chat_id: ChatId,
+    user: &User,
+    settings: &Settings,
+) -> HandlerResult {
+    // Skip showing CAPTCHA for ignored users.
+    if redis::is_ignored(chat_id, user.id).await {
+        log::info!("Ignoring user {} in chat {}", user.id, chat_id);
+        return Ok(());
+    }
+
+    // Don't flood the chat with CAPTCHAs during a raid, legit users can join again later.
+    if is_flooding(bot, chat_id, user.id, settings).await? {
+        utils::apply_fail_action(bot, chat_id, user.id, FailAction::Kick).await;
+        return Ok(());
+    }
+
+    let user_tag = html::user_mention_or_link(user);
+
+    // CAPTCHA is generated when the user follows the link, see `start_handler`.
+    if settings.captcha_location == CaptchaLocation::Private {
+        let text = t!(
+            "captcha-private-notice",
+            settings.language,
+            user_tag = user_tag,
+            duration = settings.captcha_expire.get()
+        );
+        let button = InlineKeyboardButton::url(
+            t!("captcha-private-button", settings.language),
+            start_link(me, chat_id, user.id),
+        );
+        let message = bot
+            .send_message(chat_id, text)
+            .reply_markup(InlineKeyboardMarkup::new([[button]]))
+            .await?;
+        return save_captcha(
             chat_id,
-            user.id,
-            comb.answer,
-            settings.captcha_expire.get(),
-            settings.ignore_expire.get(),
+            user,
+            &message,
+            String::new(),
+            CaptchaKind::Member,
+            settings,
+        )
+        .await;
+    }
+
+    let mut captcha = Captcha::generate(chat_id, settings).await;
+    let caption = |captcha: &Captcha| {
+        t!(
+            "captcha-caption",
+            settings.language,
+            user_tag = user_tag.clone(),
+            task = captcha.task(settings.language),
+            duration = settings.captcha_expire.get()
+        )
+    };
+    let message = captcha.send(bot, chat_id, settings, None, caption).await?;
+    captcha.sent(&message).await;
+    save_captcha(
+        chat_id,
+        user,
+        &message,
+        captcha.answer,
+        CaptchaKind::Member,
+        settings,
+    )
+    .await
+}
+
+/// Whether the chat is being raided or the user gets too many CAPTCHAs, counts the user's join.
+/// Locks the chat down when there are too many joins.
+async fn is_flooding(
+    bot: &Bot,
+    chat_id: ChatId,
+    user_id: UserId,
+    settings: &Settings,
+) -> Result<bool, ::redis::RedisError> {
+    let joins = redis::count_join(chat_id).await?;
+    if settings.lockdown_threshold > 0 && joins > settings.lockdown_threshold.into() {
+        let started = lockdown::start(bot, chat_id, settings.lockdown_duration()).await;
+        if let Err(error) = started {
+            log::error!("Failed to lock chat {chat_id} down: {error}");
+        }
+    }
+    if settings.raid_threshold > 0 && joins > settings.raid_threshold.into() {
+        log::warn!("Raid in chat {chat_id}: {joins} joins this minute, skipping user {user_id}");
+        return Ok(true);
+    }
+
+    let captchas = redis::count_user_captcha(user_id).await?;
+    if captchas > MAX_USER_CAPTCHAS {
+        log::warn!("User {user_id} got {captchas} CAPTCHAs this minute, skipping");
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+/// Link to private chat with the bot which starts CAPTCHA of the user in `chat_id`.
+fn start_link(me: &Me, chat_id: ChatId, user_id: UserId) -> url::Url {
+    let mut url = me.tme_url();
+    url.set_query(Some(&format!("start={chat_id}_{user_id}")));
+    url
+}
+
+/// Parses parameter of the `/start` command sent by following `start_link`.
+fn parse_start_link(msg: Message) -> Option<(ChatId, UserId)> {
+    let param = msg.text()?.strip_prefix("/start ")?;
+    let (chat_id, user_id) = param.trim().split_once('_')?;
+    Some((ChatId(chat_id.parse().ok()?), UserId(user_id.parse().ok()?)))
+}
+
+/// Shows CAPTCHA in private chat to a user who followed the link from the group notice.
+async fn start_handler(
+    bot: Bot,
+    msg: Message,
+    (chat_id, user_id): (ChatId, UserId),
+) -> HandlerResult {
+    let settings = settings::get(chat_id);
+    let pending = redis::get_answer(chat_id, user_id).await?;
+    let is_user = msg.from().map(|user| user.id) == Some(user_id);
+
+    let pending = match pending {
+        Some(pending) if is_user && pending.kind == CaptchaKind::Member => pending,
+        _ => {
+            let text = t!("captcha-private-not-found", settings.language);
+            bot.send_message(msg.chat.id, text).await?;
+            return Ok(());
+        }
+    };
+
+    if redis::count_user_captcha(user_id).await? > MAX_USER_CAPTCHAS {
+        let text = t!("captcha-too-many", settings.language);
+        bot.send_message(msg.chat.id, text).await?;
+        return Ok(());
+    }
+
+    // Following the link again replaces shown CAPTCHA, remaining attempts are kept.
+    if !pending.answer.is_empty() {
+        let _ = bot.delete_message(msg.chat.id, pending.message_id).await;
+    }
+
+    let mut captcha = Captcha::generate(chat_id, &settings).await;
+    let caption = |captcha: &Captcha| {
+        t!(
+            "captcha-private-caption",
+            settings.language,
+            task = captcha.task(settings.language),
+            attempts = pending.attempts
         )
+    };
+    let message = captcha
+        .send(&bot, msg.chat.id, &settings, Some(chat_id), caption)
         .await?;
+    captcha.sent(&message).await;
+    redis::update_answer(chat_id, user_id, &captcha.answer, message.id).await?;
+    redis::set_private_captcha(chat_id, user_id, message.id).await?;
+
+    Ok(())
+}
+
+/// Asks a user who requested to join the chat to solve CAPTCHA in private chat with the bot.
+async fn join_request_handler(bot: Bot, request: ChatJoinRequest) -> HandlerResult {
+    let chat_id = request.chat.id;
+    let user = &request.from;
+    let settings = settings::get(chat_id);
+
+    if !settings.approve_join_requests {
+        return Ok(());
+    }
+
+    if redis::is_ignored(chat_id, user.id).await {
+        log::info!(
+            "Declining join request of ignored user {} to {}",
+            user.id,
+            chat_id
+        );
+        bot.decline_chat_join_request(chat_id, user.id).await?;
+        return Ok(());
+    }
+
+    if is_flooding(&bot, chat_id, user.id, &settings).await? {
+        bot.decline_chat_join_request(chat_id, user.id).await?;
+        return Ok(());
     }
 
+    let mut captcha = Captcha::generate(chat_id, &settings).await;
+    let chat_title = html::escape(request.chat.title().unwrap_or_default());
+    let caption = |captcha: &Captcha| {
+        t!(
+            "captcha-join-caption",
+            settings.language,
+            chat_title = chat_title.clone(),
+            task = captcha.task(settings.language),
+            duration = settings.captcha_expire.get()
+        )
+    };
+    let message = captcha
+        .send(
+            &bot,
+            ChatId::from(user.id),
+            &settings,
+            Some(chat_id),
+            caption,
+        )
+        .await?;
+    captcha.sent(&message).await;
+    save_captcha(
+        chat_id,
+        user,
+        &message,
+        captcha.answer,
+        CaptchaKind::JoinRequest,
+        &settings,
+    )
+    .await
+}
+
+/// Remembers answer to the CAPTCHA sent in `message` and schedules its expiration.
+async fn save_captcha(
+    chat_id: ChatId,
+    user: &User,
+    message: &Message,
+    answer: String,
+    kind: CaptchaKind,
+    settings: &Settings,
+) -> HandlerResult {
+    let pending = redis::PendingCaptcha {
+        chat_id,
+        user_id: user.id,
+        message_chat_id: message.chat.id,
+        message_id: message.id,
+        kind,
+        user_tag: html::user_mention_or_link(user),
+        private_message_id: None,
+    };
+    scheduler::schedule_captcha(&pending, settings.captcha_expire()).await?;
+    let answer = redis::Answer {
+        answer,
+        attempts: settings.captcha_attempts,
+        message_id: message.id,
+        kind,
+    };
+    redis::set_answer(
+        chat_id,
+        user.id,
+        &answer,
+        settings.captcha_expire.get(),
+        settings.ignore_expire.get(),
+    )
+    .await?;
+
     Ok(())
 }
 
@@ -520,63 +1659,237 @@ async fn captcha_response_handler(bot: Bot, query: CallbackQuery) -> HandlerResu
         return Ok(());
     }
 
-    let answer = query.data.unwrap();
+    let data = query.data.unwrap();
     let message = query.message.unwrap();
-    let chat_id = message.chat.id;
     let user_id = query.from.id;
+
+    // Answers to CAPTCHA in private chat are prefixed with the group chat id.
+    let (chat_id, answer) = if message.chat.is_private() {
+        let chat_id_answer = data
+            .split_once(':')
+            .and_then(|(id, answer)| Some((ChatId(id.parse().ok()?), answer.to_owned())));
+        match chat_id_answer {
+            Some(chat_id_answer) => chat_id_answer,
+            None => return Ok(()),
+        }
+    } else {
+        (message.chat.id, data)
+    };
+
     let settings = settings::get(chat_id);
-    let correct_answer = redis::get_answer(chat_id, user_id).await?;
+    let pending = redis::get_answer(chat_id, user_id).await?;
 
-    if correct_answer.is_none() {
+    if pending.is_none() || pending.as_ref().unwrap().message_id != message.id {
         let text = t!("query-wrong-user", settings.language);
         bot.answer_callback_query(query.id).text(text).await?;
         return Ok(());
     }
 
-    let correct_answer = correct_answer.unwrap();
+    let pending = pending.unwrap();
     let user_tag = html::user_mention_or_link(&query.from);
+    let private_chat_id = message.chat.is_private().then_some(chat_id);
 
-    if *answer == correct_answer {
-        let text = t!("query-correct", settings.language);
-        let (restriction, _, _) = tokio::join!(
-            bot.restrict_chat_member(chat_id, user_id, ChatPermissions::all())
-                .into_future(),
-            bot.answer_callback_query(query.id).text(text).into_future(),
-            bot.delete_message(chat_id, message.id).into_future()
+    if answer != pending.answer && pending.attempts > 1 {
+        // Show a new CAPTCHA in place of the old one.
+        let attempts = pending.attempts - 1;
+        let text = t!("query-wrong", settings.language);
+        let (mut captcha, _) = tokio::join!(
+            Captcha::generate(chat_id, &settings),
+            bot.answer_callback_query(query.id).text(text).into_future()
         );
+        let caption = |captcha: &Captcha| {
+            t!(
+                "captcha-caption-retry",
+                settings.language,
+                user_tag = user_tag.clone(),
+                task = captcha.task(settings.language),
+                attempts = attempts
+            )
+        };
+        let shown = if captcha.fits(&message) {
+            let keyboard = captcha.keyboard(&settings, private_chat_id);
+            captcha
+                .edit(&bot, &message, caption(&captcha), keyboard)
+                .await?
+        } else {
+            // E.g. photo can't be edited into text, so CAPTCHA is sent as a new message.
+            let _ = bot.delete_message(message.chat.id, message.id).await;
+            let shown = captcha
+                .send(&bot, message.chat.id, &settings, private_chat_id, caption)
+                .await?;
+            redis::move_pending_captcha(chat_id, user_id, &message, shown.id).await?;
+            shown
+        };
+        captcha.sent(&shown).await;
+        redis::retry_answer(chat_id, user_id, &captcha.answer, shown.id).await?;
+        return Ok(());
+    }
 
-        // Show error if restriction didn't work.
-        if let Err(error) = restriction {
-            let message = format!("Failed to restrict user: {error}");
-            log::error!("{message}");
-            bot.send_message(chat_id, message).await?;
-            return Ok(());
+    redis::delete_answer(chat_id, user_id).await?;
+    // CAPTCHA has just expired.
+    let Some(pending_captcha) = redis::take_pending_captcha(chat_id, user_id).await? else {
+        return Ok(());
+    };
+    // Delete the notice in the group when CAPTCHA was answered in private.
+    if pending_captcha.message_id != message.id {
+        let _ = bot
+            .delete_message(pending_captcha.message_chat_id, pending_captcha.message_id)
+            .await;
+    }
+
+    let text = if answer == pending.answer {
+        t!("query-correct", settings.language)
+    } else {
+        t!("query-wrong", settings.language)
+    };
+    let _ = tokio::join!(
+        bot.answer_callback_query(query.id).text(text).into_future(),
+        bot.delete_message(message.chat.id, message.id)
+            .into_future()
+    );
+
+    match (pending.kind, answer == pending.answer) {
+        (CaptchaKind::Member, true) => {
+            // Restore chat's default permissions instead of granting all of them.
+            let permissions = utils::default_permissions(&bot, chat_id).await;
+            let restriction = bot
+                .restrict_chat_member(chat_id, user_id, permissions)
+                .await;
+
+            // Show error if restriction didn't work.
+            if let Err(error) = restriction {
+                let message = format!("Failed to restrict user: {error}");
+                log::error!("{message}");
+                bot.send_message(chat_id, message).await?;
+                return Ok(());
+            }
+
+            if message.chat.is_private() {
+                let text = t!("captcha-private-passed", settings.language);
+                bot.send_message(message.chat.id, text).await?;
+            }
+            start_probation(chat_id, user_id, &settings).await?;
+            greet(&bot, chat_id, user_tag, &settings).await?;
+        }
+        (CaptchaKind::Member, false) => {
+            utils::apply_fail_action(&bot, chat_id, user_id, settings.fail_action).await;
+            let text = t!(
+                "captcha-incorrect-answer",
+                settings.language,
+                user_tag = user_tag,
+                duration = settings.ignore_expire.get()
+            );
+            let message = bot.send_message(chat_id, text).await?;
+            utils::delete_message_later(&bot, chat_id, message.id, settings.message_expire());
+        }
+        (CaptchaKind::JoinRequest, true) => {
+            // Approved user joins the chat right away, don't show CAPTCHA again.
+            redis::mark_joined(chat_id, user_id).await?;
+            bot.approve_chat_join_request(chat_id, user_id).await?;
+            let text = t!("captcha-join-approved", settings.language);
+            bot.send_message(message.chat.id, text).await?;
+            start_probation(chat_id, user_id, &settings).await?;
+            greet(&bot, chat_id, user_tag, &settings).await?;
+        }
+        (CaptchaKind::JoinRequest, false) => {
+            bot.decline_chat_join_request(chat_id, user_id).await?;
+            let text = t!(
+                "captcha-join-incorrect-answer",
+                settings.language,
+                duration = settings.ignore_expire.get()
+            );
+            bot.send_message(message.chat.id, text).await?;
         }
+    }
 
-        let text = if let Some(text) = settings::get_greeting(chat_id) {
-            let mut vars = HashMap::new();
-            vars.insert("user_tag".to_string(), user_tag);
-            strfmt(&text, &vars).unwrap()
-        } else {
-            t!("greeting", settings.language, user_tag)
-        };
-        let message = bot.send_message(chat_id, text).await?;
-        let _ = utils::delete_message_later(&bot, chat_id, message.id, settings.message_expire());
+    Ok(())
+}
+
+/// Checks first messages of the user who passed CAPTCHA for spam, see `newcomer_message_handler`.
+async fn start_probation(chat_id: ChatId, user_id: UserId, settings: &Settings) -> HandlerResult {
+    if settings.newcomer_policy != NewcomerPolicy::Off {
+        redis::start_probation(chat_id, user_id, settings.newcomer_messages).await?;
+    }
+    Ok(())
+}
+
+async fn greet(bot: &Bot, chat_id: ChatId, user_tag: String, settings: &Settings) -> HandlerResult {
+    let text = if let Some(text) = settings::get_greeting(chat_id) {
+        let mut vars = HashMap::new();
+        vars.insert("user_tag".to_string(), user_tag);
+        strfmt(&text, &vars).unwrap()
     } else {
-        let text = t!("query-wrong", settings.language);
-        let _ = tokio::join!(
-            bot.answer_callback_query(query.id).text(text).into_future(),
-            bot.delete_message(chat_id, message.id).into_future()
-        );
-        let text = t!(
-            "captcha-incorrect-answer",
-            settings.language,
-            user_tag = user_tag,
-            duration = settings.ignore_expire.get()
+        t!("greeting", settings.language, user_tag)
+    };
+    let message = bot.send_message(chat_id, text).await?;
+    utils::delete_message_later(bot, chat_id, message.id, settings.message_expire());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Option<Command> {
+        Command::parse(&normalize_command(text), "guardian_bot").ok()
+    }
+
+    #[test]
+    fn command_args_after_any_whitespace() {
+        for text in [
+            "/set captcha_expire 2m",
+            "/set\ncaptcha_expire 2m",
+            "/set@guardian_bot\tcaptcha_expire\n2m",
+            "/set  \n captcha_expire   2m",
+        ] {
+            let Some(Command::Set(args)) = parse(text) else {
+                panic!("{text:?} isn't parsed as /set");
+            };
+            let (key, value) = args.trim().split_once(char::is_whitespace).unwrap();
+            assert_eq!((key, value.trim()), ("captcha_expire", "2m"));
+        }
+
+        assert!(matches!(parse("/get\nlanguage"), Some(Command::Get(key)) if key == "language"));
+        assert!(
+            matches!(parse("/reset\nlanguage\n"), Some(Command::Reset(key)) if key.trim() == "language")
         );
-        let message = bot.send_message(chat_id, text).await?;
-        let _ = utils::delete_message_later(&bot, chat_id, message.id, settings.message_expire());
+        assert!(matches!(parse("/get"), Some(Command::Get(key)) if key.is_empty()));
+        assert!(matches!(parse("/settings\n"), Some(Command::Settings)));
+        assert!(parse("/set@other_bot\nlanguage ru").is_none());
     }
 
-    Ok(())
+    #[test]
+    fn commands_are_snake_case() {
+        assert!(matches!(
+            parse("/delete_question 2"),
+            Some(Command::DeleteQuestion(2))
+        ));
+        assert!(matches!(
+            parse("/settings_export"),
+            Some(Command::SettingsExport)
+        ));
+        assert!(matches!(
+            parse("/settings_copy -100"),
+            Some(Command::SettingsCopy(_))
+        ));
+        assert!(parse("/deleteQuestion 2").is_none());
+    }
+
+    #[test]
+    fn import_json_on_next_line() {
+        let json = "{\n  \"settings\": {\n    \"language\": \"ru\"\n  }\n}";
+        for text in [
+            format!("/settings_import\n{json}"),
+            format!("/settings_import@guardian_bot\r\n{json}"),
+            format!("/settings_import {json}"),
+        ] {
+            let Some(Command::SettingsImport(args)) = parse(&text) else {
+                panic!("{text:?} isn't parsed as /settings_import");
+            };
+            assert_eq!(args, json);
+            let document: export::Document = serde_json::from_str(&args).unwrap();
+            assert_eq!(document.settings["language"], "ru");
+        }
+    }
 }
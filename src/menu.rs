@@ -0,0 +1,175 @@
+use crate::settings::{Choice, RawSetting, Settings, KEYS};
+use crate::t;
+use std::fmt;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+const PREFIX: &str = "settings";
+const OPTIONS_ROW_SIZE: usize = 4;
+
+/// What a button of the settings menu does, encoded in its callback data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Shows the list of settings.
+    Main,
+    /// Shows options of the setting.
+    Open(String),
+    /// Sets the setting to the value.
+    Set(String, String),
+    /// Asks admin to send a value of the setting.
+    Input(String),
+    Close,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Main => write!(f, "{PREFIX}:main"),
+            Self::Open(key) => write!(f, "{PREFIX}:open:{key}"),
+            Self::Set(key, value) => write!(f, "{PREFIX}:set:{key}:{value}"),
+            Self::Input(key) => write!(f, "{PREFIX}:input:{key}"),
+            Self::Close => write!(f, "{PREFIX}:close"),
+        }
+    }
+}
+
+impl Action {
+    pub fn parse(data: &str) -> Option<Self> {
+        let data = data.strip_prefix(PREFIX)?.strip_prefix(':')?;
+        let (action, args) = data.split_once(':').unwrap_or((data, ""));
+        let action = match action {
+            "main" => Self::Main,
+            "open" => Self::Open(args.to_owned()),
+            "set" => {
+                // Values may contain colons, e.g. "ban_for:3600".
+                let (key, value) = args.split_once(':')?;
+                Self::Set(key.to_owned(), value.to_owned())
+            }
+            "input" => Self::Input(args.to_owned()),
+            "close" => Self::Close,
+            _ => return None,
+        };
+
+        Some(action)
+    }
+}
+
+fn button(text: String, action: Action) -> InlineKeyboardButton {
+    InlineKeyboardButton::callback(text, action.to_string())
+}
+
+/// Lists settings with their values, booleans are toggled right from the list.
+pub fn main_keyboard(settings: &Settings) -> InlineKeyboardMarkup {
+    let mut rows: Vec<_> = KEYS
+        .iter()
+        .map(|key| {
+            let value = RawSetting::value(settings, key).unwrap_or_default();
+            let action = match RawSetting::choice(key) {
+                Some(Choice::Toggle) => Action::Set(key.to_string(), (value != "true").to_string()),
+                _ => Action::Open(key.to_string()),
+            };
+            let value = RawSetting::display(settings, key).unwrap_or_default();
+            vec![button(format!("{key}: {value}"), action)]
+        })
+        .collect();
+    let text = t!("settings-button-close", settings.language);
+    rows.push(vec![button(text, Action::Close)]);
+
+    InlineKeyboardMarkup::new(rows)
+}
+
+/// Options of the setting with the current one marked, `None` for unknown settings and toggles.
+pub fn options_keyboard(settings: &Settings, key: &str) -> Option<InlineKeyboardMarkup> {
+    let Some(Choice::Options { options, custom }) = RawSetting::choice(key) else {
+        return None;
+    };
+    let current = RawSetting::value(settings, key)?;
+
+    let buttons: Vec<_> = options
+        .into_iter()
+        .map(|value| {
+            let text = if value == current {
+                format!("• {value} •")
+            } else {
+                value.clone()
+            };
+            button(text, Action::Set(key.to_owned(), value))
+        })
+        .collect();
+    let mut rows: Vec<_> = buttons
+        .chunks(OPTIONS_ROW_SIZE)
+        .map(|row| row.to_vec())
+        .collect();
+
+    if custom {
+        let text = t!("settings-button-custom", settings.language);
+        rows.push(vec![button(text, Action::Input(key.to_owned()))]);
+    }
+    let text = t!("settings-button-back", settings.language);
+    rows.push(vec![button(text, Action::Main)]);
+
+    Some(InlineKeyboardMarkup::new(rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every action the menu may encode in a button.
+    fn all_actions() -> Vec<Action> {
+        let mut actions = vec![Action::Main, Action::Close];
+        for key in KEYS {
+            actions.push(Action::Open(key.to_owned()));
+            actions.push(Action::Input(key.to_owned()));
+            let values = match RawSetting::choice(key) {
+                Some(Choice::Toggle) => vec!["true".to_owned(), "false".to_owned()],
+                Some(Choice::Options { options, .. }) => options,
+                None => vec![],
+            };
+            for value in values {
+                actions.push(Action::Set(key.to_owned(), value));
+            }
+        }
+        actions
+    }
+
+    #[test]
+    fn actions_round_trip() {
+        for action in all_actions() {
+            assert_eq!(Action::parse(&action.to_string()), Some(action));
+        }
+    }
+
+    #[test]
+    fn actions_fit_callback_data() {
+        for action in all_actions() {
+            let data = action.to_string();
+            assert!(data.len() <= 64, "{data} is longer than 64 bytes");
+        }
+    }
+
+    #[test]
+    fn parse_value_with_colons() {
+        assert_eq!(
+            Action::parse("settings:set:fail_action:ban_for:86400"),
+            Some(Action::Set("fail_action".into(), "ban_for:86400".into()))
+        );
+        let action = Action::Set("fail_action".into(), "ban_for:1d".into());
+        assert_eq!(action.to_string(), "settings:set:fail_action:ban_for:1d");
+    }
+
+    #[test]
+    fn parse_unknown_data() {
+        for data in [
+            "",
+            "settings",
+            "settings:",
+            "settings:unknown",
+            "settings:set:fail_action",
+            "settingsmain",
+            "other:main",
+            "123:456:1",
+        ] {
+            assert_eq!(Action::parse(data), None, "{data}");
+        }
+    }
+}
@@ -1,55 +1,237 @@
+use crate::settings::MAX_CAPTCHA_OPTIONS;
 use rand::{rngs::ThreadRng, seq::SliceRandom, thread_rng, Rng};
-use std::fmt;
-use Group::*;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::{error, fmt};
+use tokio::{
+    fs,
+    signal::unix::{signal, SignalKind},
+};
 
-struct Query(&'static [&'static str], &'static [&'static str]);
+/// Catalog embedded into the binary, used when `QNA_PATH` isn't set.
+const DEFAULT_CATALOG: &str = include_str!("../qna.json");
+/// Catalog must have enough groups to fill a CAPTCHA keyboard.
+const MIN_GROUPS: usize = MAX_CAPTCHA_OPTIONS as usize;
+static GROUPS: RwLock<Vec<Group>> = RwLock::new(Vec::new());
 
-impl Query {
-    fn pick_emoji(&self, rng: &mut ThreadRng) -> &'static str {
-        if self.0.len() == 1 {
-            self.0[0]
-        } else {
-            self.0.choose(rng).unwrap()
+#[derive(Debug, Deserialize)]
+struct Query {
+    emojis: Vec<String>,
+    phrases: Vec<String>,
+}
+
+/// Queries of a `Multiple` group are alike, so only one of them is used in a CAPTCHA.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Group {
+    Single(Query),
+    Multiple(Vec<Query>),
+}
+
+impl Group {
+    fn queries(&self) -> &[Query] {
+        match self {
+            Group::Single(query) => std::slice::from_ref(query),
+            Group::Multiple(queries) => queries,
+        }
+    }
+
+    fn has_emoji(&self, emoji: &str) -> bool {
+        self.queries()
+            .iter()
+            .any(|query| query.emojis.iter().any(|e| same_emoji(e, emoji)))
+    }
+}
+
+/// Emojis with and without variation selector look the same.
+fn normalize_emoji(emoji: &str) -> String {
+    emoji.trim().replace('\u{fe0f}', "")
+}
+
+pub fn same_emoji(a: &str, b: &str) -> bool {
+    normalize_emoji(a) == normalize_emoji(b)
+}
+
+fn normalize_phrase(phrase: &str) -> String {
+    phrase.trim().to_lowercase()
+}
+
+/// Picture of a CAPTCHA, either searched by a phrase or uploaded to Telegram beforehand.
+#[derive(Debug, Clone)]
+pub enum Picture {
+    Phrase(String),
+    File(String),
+}
+
+impl Picture {
+    /// Pictures with equal keys can't be told apart.
+    fn key(&self) -> String {
+        match self {
+            Self::Phrase(phrase) => normalize_phrase(phrase),
+            Self::File(file_id) => file_id.clone(),
         }
     }
+}
 
-    fn pick_phrase(&self, rng: &mut ThreadRng) -> &'static str {
-        if self.1.len() == 1 {
-            self.1[0]
-        } else {
-            self.1.choose(rng).unwrap()
+impl fmt::Display for Picture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Phrase(phrase) => write!(f, "phrase \"{phrase}\""),
+            Self::File(file_id) => write!(f, "file \"{file_id}\""),
         }
     }
 }
 
+/// Question added by admins of a chat.
+#[derive(Debug, Clone)]
+pub struct CustomQuery {
+    pub id: i64,
+    pub emoji: String,
+    pub picture: Picture,
+}
+
 pub struct Combination {
-    pub emojis: Box<[&'static str]>,
-    pub answer: &'static str,
-    pub query_phrase: &'static str,
+    pub emojis: Vec<String>,
+    pub answer: String,
+    pub picture: Picture,
+}
+
+/// Variants of a combination which can be told apart: buttons are neither empty nor repeated
+/// and no two variants are answers to the same picture.
+#[derive(Default)]
+struct Variants {
+    list: Vec<(String, Picture)>,
+    emojis: HashSet<String>,
+    pictures: HashSet<String>,
+}
+
+impl Variants {
+    /// Returns `false` if the variant is indistinguishable from the added ones.
+    fn add(&mut self, emoji: &str, picture: &Picture) -> bool {
+        let emoji_key = normalize_emoji(emoji);
+        let picture_key = picture.key();
+        if emoji_key.is_empty()
+            || picture_key.is_empty()
+            || self.emojis.contains(&emoji_key)
+            || self.pictures.contains(&picture_key)
+        {
+            return false;
+        }
+
+        self.emojis.insert(emoji_key);
+        self.pictures.insert(picture_key);
+        self.list.push((emoji.trim().to_owned(), picture.clone()));
+        true
+    }
+
+    /// Adds a variant of the group, trying its queries, emojis and phrases in random order
+    /// until one can be told apart from the added variants.
+    fn add_group(&mut self, group: &Group, rng: &mut ThreadRng) -> bool {
+        let queries = group.queries();
+        for query in queries.choose_multiple(rng, queries.len()) {
+            for emoji in query.emojis.choose_multiple(rng, query.emojis.len()) {
+                for phrase in query.phrases.choose_multiple(rng, query.phrases.len()) {
+                    if self.add(emoji, &Picture::Phrase(phrase.to_owned())) {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+#[derive(Debug)]
+pub struct NotEnoughVariantsError {
+    pub found: usize,
+    pub required: usize,
+}
+
+impl fmt::Display for NotEnoughVariantsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "only {} of {} CAPTCHA variants can be told apart",
+            self.found, self.required
+        )
+    }
 }
 
+impl error::Error for NotEnoughVariantsError {}
+
 impl Combination {
-    pub fn pick(queries_amount: usize) -> Combination {
+    /// Up to half of the variants are taken from chat's `custom` queries, the rest from the catalog.
+    /// Fails when there aren't `queries_amount` variants which can be told apart.
+    pub fn pick(
+        queries_amount: usize,
+        custom: &[CustomQuery],
+    ) -> Result<Combination, NotEnoughVariantsError> {
+        Self::pick_from(&GROUPS.read().unwrap(), queries_amount, custom)
+    }
+
+    fn pick_from(
+        groups: &[Group],
+        queries_amount: usize,
+        custom: &[CustomQuery],
+    ) -> Result<Combination, NotEnoughVariantsError> {
+        let variants = Self::pick_variants(groups, queries_amount, custom)?;
+
+        let answer_idx = thread_rng().gen_range(0..variants.len());
+        let (answer, picture) = variants[answer_idx].clone();
+        let emojis = variants.into_iter().map(|(emoji, _)| emoji).collect();
+
+        Ok(Combination {
+            emojis,
+            answer,
+            picture,
+        })
+    }
+
+    /// Emojis of the buttons in random order with pictures they answer.
+    fn pick_variants(
+        groups: &[Group],
+        queries_amount: usize,
+        custom: &[CustomQuery],
+    ) -> Result<Vec<(String, Picture)>, NotEnoughVariantsError> {
         let mut rng = thread_rng();
-        let queries: Box<[&Query]> = GROUPS
-            .choose_multiple(&mut rng, queries_amount)
-            .map(|group| match group {
-                Single(query) => query,
-                Multiple(queries) => queries.choose(&mut rng).unwrap(),
-            })
-            .collect();
-        let emojis: Box<[&str]> = queries
+        let custom_amount = custom.len().min(queries_amount / 2);
+        let mut variants = Variants::default();
+        for query in custom.choose_multiple(&mut rng, custom.len()) {
+            if variants.list.len() == custom_amount {
+                break;
+            }
+            variants.add(&query.emoji, &query.picture);
+        }
+
+        let mut groups: Vec<&Group> = groups
             .iter()
-            .map(|query| query.pick_emoji(&mut rng))
+            .filter(|group| {
+                !variants
+                    .list
+                    .iter()
+                    .any(|(emoji, _)| group.has_emoji(emoji))
+            })
             .collect();
-        let answer_idx = rng.gen_range(0..queries_amount);
-        let query_phrase = queries[answer_idx].pick_phrase(&mut rng);
+        groups.shuffle(&mut rng);
+        for group in groups {
+            if variants.list.len() == queries_amount {
+                break;
+            }
+            variants.add_group(group, &mut rng);
+        }
 
-        Combination {
-            answer: &emojis[answer_idx],
-            emojis,
-            query_phrase,
+        if variants.list.is_empty() || variants.list.len() < queries_amount {
+            return Err(NotEnoughVariantsError {
+                found: variants.list.len(),
+                required: queries_amount,
+            });
         }
+
+        let mut variants = variants.list;
+        variants.shuffle(&mut rng);
+        Ok(variants)
     }
 }
 
@@ -57,177 +239,241 @@ impl fmt::Display for Combination {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "Variants: {:?}, Answer: \"{}\", Query phrase: \"{}\"",
-            self.emojis, self.answer, self.query_phrase
+            "Variants: {:?}, Answer: \"{}\", Picture: {}",
+            self.emojis, self.answer, self.picture
         )
     }
 }
 
-enum Group {
-    Single(Query),
-    Multiple(&'static [Query]),
-}
-
-#[rustfmt::skip]
-const GROUPS: [Group; 109] = [
-    Single(Query(&["😎", "🕶"], &["people in sunglasses", "sunglasses"])),
-    Single(Query(&["🥳", "🎉", "🎊"], &["birthday party children", "people on a party"])),
-    Single(Query(&["😤", "😠", "😡"], &["angry man", "angry woman"])),
-    Single(Query(&["🤗", "👐", "👐🏻", "👐🏼"], &["hug", "hugging"])),
-    Single(Query(&["🤫"], &["shushing man", "shushing woman"])),
-    Single(Query(&["🤔"], &["thinking man"])),
-    Single(Query(&["🫡"], &["saluting army man", "saluting army woman"])),
-    Single(Query(&["😴", "💤", "🛌"], &["sleep"])),
-    Single(Query(&["🥴", "🍺", "🍻", "🥂", "🥃"], &["drunk man", "drunk woman", "drinking alcohol"])),
-    Single(Query(&["🤢", "🤮"], &["vomit in cartoon"])),
-    Single(Query(&["🤧", "😷", "🤒", "🤕"], &["sick man"])),
-    Single(Query(&["🤑", "💰", "💸", "💵"], &["money economics", "dollars", "euros"])),
-    Single(Query(&["💥", "💣️"], &["bomb explodes cartoonish"])),
-    Single(Query(&["💫", "🌠"], &["shootingstar"])),
-    Single(Query(&["👌", "👌🏻", "👌🏼", "🆗"], &["ok hand"])),
-    Single(Query(&["✌️", "✌🏻", "✌🏼"], &["showing victory hand"])),
-    Single(Query(&["🤞", "🤞🏻", "🤞🏼"], &["showing crossed fingers"])),
-    Single(Query(&["🤘", "🤘🏻", "🤘🏼"], &["people showing rock hand"])),
-    Single(Query(&["✊", "✊🏻", "✊🏼"], &["raised fist"])),
-    Single(Query(&["👏", "👏🏻", "👏🏼"], &["people clapping hands"])),
-    Single(Query(&["🙌", "🙌🏻", "🙌🏼"], &["people raising hands up"])),
-    Single(Query(&["🤝", "🤝🏻", "🤝🏼", "🫱🏻‍🫲🏼", "🫱🏻‍🫲🏽", "🫱🏼‍🫲🏻", ""], &["people handshake"])),
-    Single(Query(&["🧠"], &["brain pictures"])),
-    Single(Query(&["🦷"], &["tooth"])),
-    Single(Query(&["🦴"], &["bone"])),
-    Single(Query(&["👀", "👁️"], &["eyes"])),
-    Single(Query(&["😛", "👅"], &["tongue"])),
-    Single(Query(&["🙅", "🙅🏻", "🙅🏼", "🙅‍♂️", "🙅🏻‍♂️", "🙅🏼‍♂️", "🙅‍♀️", "🙅🏻‍♀️", "🙅🏼‍♀️", "❌"], &["people gesturing no crossing hands"])),
-    Single(Query(&["🧑‍🎓", "🧑🏻‍🎓", "🧑🏼‍🎓", "👨‍🎓", "👨🏻‍🎓", "👨🏼‍🎓", "👩‍🎓", "👩🏻‍🎓", "👩🏼‍🎓"], &["students wearing graduate caps"])),
-    Single(Query(&["🧑‍🍳", "🧑🏻‍🍳", "🧑🏼‍🍳", "👨‍🍳", "👨🏻‍🍳", "👨🏼‍🍳", "👩‍🍳", "👩🏻‍🍳", "👩🏼‍🍳"], &["professional cook wearing white with food"])),
-    Single(Query(&["👷", "👷🏻", "👷🏼", "👷‍♂️", "👷🏻‍♂️", "👷🏼‍♂️", "👷‍♀️", "👷🏻‍♀️", "👷🏼‍♀️"], &["construction worker"])),
-    Single(Query(&["🧙", "🧙🏻", "🧙🏼", "🧙‍♂️", "🧙🏻‍♂️", "🧙🏼‍♂️", "🧙‍♀️", "🧙🏻‍♀️", "🧙🏼‍♀️"], &["wizard"])),
-    Single(Query(&["🧟", "🧟‍♂️", "🧟‍♀️"], &["zombie in cartoon"])),
-    Single(Query(&["🏃", "🏃🏻", "🏃🏼", "🏃‍♂️", "🏃🏻‍♂️", "🏃🏼‍♂️", "🏃‍♀️", "🏃🏻‍♀️", "🏃🏼‍♀️"], &["running"])),
-    Single(Query(&["⛹️", "⛹🏻", "⛹🏼", "⛹️‍♂️", "⛹🏻‍♂️", "⛹🏼‍♂️", "⛹️‍♀️", "⛹🏻‍♀️", "⛹🏼‍♀️"], &["playing basketball"])),
-    Single(Query(&["🚴", "🚴🏻", "🚴🏼", "🚴‍♂️", "🚴🏻‍♂️", "🚴🏼‍♂️", "🚴‍♀️", "🚴🏻‍♀️", "🚴🏼‍♀️"], &["biking"])),
-    Single(Query(&["🧘", "🧘🏻", "🧘🏼", "🧘‍♂️", "🧘🏻‍♂️", "🧘🏼‍♂️", "🧘‍♀️", "🧘🏻‍♀️", "🧘🏼‍♀️"], &["people in lotus position"])),
-    Single(Query(&["🍌"], &["banana", "eat banana"])),
-    Multiple(&[
-        Query(&["🐶", "🐕️", "🦮", "🐩"], &["dog"]),
-        Query(&["🐺"], &["wolf"]),
-    ]),
-    Single(Query(&["🦊"], &["fox"])),
-    Multiple(&[
-        Query(&["🙉", "🐵", "🐒"], &["monkey"]),
-        Query(&["🦍"], &["gorilla"]),
-        Query(&["🦧"], &["gorilla"]),
-        ]),
-    Single(Query(&["🐮", "🐄"], &["cow"])),
-    Single(Query(&["🐷", "🐖"], &["pig"])),
-    Multiple(&[
-        Query(&["🐏"], &["ram"]),
-        Query(&["🐑"], &["ewe"]),
-    ]),
-    Multiple(&[
-        Query(&["🦁"], &["lion"]),
-        Query(&["🐯"], &["tiger"]),
-    ]),
-    Single(Query(&["🐪", "🐫"], &["camel"])),
-    Single(Query(&["🦒"], &["giraffe"])),
-    Single(Query(&["🐘"], &["elephant"])),
-    Single(Query(&["🐰", "🐇"], &["rabbit"])),
-    Single(Query(&["🦔"], &["hedgehog"])),
-    Single(Query(&["🐻"], &["bear"])),
-    Single(Query(&["🐼"], &["panda"])),
-    Single(Query(&["🦘"], &["kangaroo"])),
-    Single(Query(&["🐔", "🐓"], &["chicken", "rooster"])),
-    Single(Query(&["🐣", "🐤", "🐥"], &["baby chick"])),
-    Single(Query(&["🐧"], &["penguin"])),
-    Single(Query(&["🦆"], &["duck with green head"])),
-    Single(Query(&["🦢"], &["swan"])),
-    Single(Query(&["🦉"], &["owl"])),
-    Single(Query(&["🦩"], &["flamingo"])),
-    Single(Query(&["🦜"], &["parrot"])),
-    Single(Query(&["🐸"], &["frog"])),
-    Single(Query(&["🐊"], &["crocodile"])),
-    Single(Query(&["🐢"], &["turtle"])),
-    Multiple(&[
-        Query(&["🐍"], &["snake"]),
-        Query(&["🪱"], &["worm"]),
-    ]),
-    Multiple(&[
-        Query(&["🦖"], &["dinosaur T-Rex"]),
-        Query(&["🦕"], &["dinosaur sauropod"]),
-    ]),
-    Multiple(&[
-        Query(&["🐳", "🐋"], &["whale", "spouting whale"]),
-        Query(&["🐬"], &["dolphin"]),
-        Query(&["🐟️"], &["fish"]),
-        Query(&["🐠"], &["tropical fish"]),
-        Query(&["🐡"], &["blowfish"]),
-        Query(&["🦈"], &["shark"]),
-    ]),
-    Single(Query(&["🐙"], &["red octopus"])),
-    Single(Query(&["🐌"], &["snail"])),
-    Single(Query(&["🦋"], &["butterfly"])),
-    Single(Query(&["🐝"], &["honeybee"])),
-    Multiple(&[
-        Query(&["🪲"], &["beetle"]),
-        Query(&["🐞"], &["lady beetle"]),
-    ]),
-    Single(Query(&["🪳"], &["cockroach"])),
-    Single(Query(&["🕸️"], &["spider web"])),
-    Single(Query(&["🦂"], &["scorpion"])),
-    Multiple(&[
-        Query(&["🌸"], &["cherry blossom"]),
-        Query(&["🪷"], &["pink lotus"]),
-        Query(&["🌹"], &["rose"]),
-        Query(&["🌺"], &["hibiscus"]),
-        Query(&["🌻"], &["sunflower"]),
-        Query(&["🌼"], &["blossom"]),
-        Query(&["🌷"], &["tulip"]),
-    ]),
-    Single(Query(&["🌱"], &["seedling"])),
-    Single(Query(&["🪴"], &["potted plant"])),
-    Multiple(&[
-        Query(&["🐭", "🐁"], &["mouse"]),
-        Query(&["🐀"], &["rat"]),
-        Query(&["🐹"], &["hamster"]),
-    ]),
-    Multiple(&[
-        Query(&["🤠"], &["cowboy"]),
-        Query(&["🐴", "🐎"], &["horse"]),
-    ]),
-    Single(Query(&["😈", "👹"], &["devil"])),
-    Single(Query(&["🤡"], &["clown"])),
-    Single(Query(&["💩"], &["poop in cartoon"])),
-    Single(Query(&["👻"], &["ghost"])),
-    Single(Query(&["💀", "☠️"], &["skull"])),
-    Single(Query(&["👽"], &["alien", "ufo"])),
-    Single(Query(&["🤖"], &["robot"])),
-    Single(Query(&["🎃"], &["halloween", "pumpkin"])),
-    Single(Query(&["😺", "🐈"], &["cat", "kitty"])),
-    Single(Query(&["🤝"], &["handshake"])),
-    Single(Query(&["👍", "👍🏻", "👍🏼"], &["people thumbs up"])),
-    Single(Query(&["👎", "👎🏻", "👎🏼"], &["people thumbs down sad"])),
-    Multiple(&[
-        Query(&["💪", "💪🏻", "💪🏼"], &["strong man in gym", "strong woman in gym"]),
-        Query(&["🏋️", "🏋🏻", "🏋🏼", "🏋️‍♂️", "🏋🏻‍♂️", "🏋🏼‍♂️", "🏋️‍♀️", "🏋🏻‍♀️", "🏋🏼‍♀️"], &["lifting weights"]),
-    ]),
-    Single(Query(&["🖕", "🖕🏻", "🖕🏼"], &["middle finger"])),
-    Single(Query(&["✍️", "✍🏻", "✍🏼"], &["writing"])),
-    Single(Query(&["🦶", "🦶🏻", "🦶🏼"], &["foot"])),
-    Single(Query(&["👂", "👂🏻", "👂🏼"], &["ear"])),
-    Single(Query(&["👃", "👃🏻", "👃🏼"], &["pictures of nose"])),
-    Single(Query(&["👶", "👶🏻", "👶🏼"], &["child", "baby"])),
-    Multiple(&[
-        Query(&["🧔🏻‍♀️", "🧔", "🧔🏻", "🧔‍♂️", "🧔🏻‍♂️"], &["beard"]),
-        Query(&["👴", "👴🏻", "👴🏼"], &["old man"]),
-        Query(&["👵", "👵🏻", "👵🏼", "🧓", "🧓🏻", "🧓🏼"], &["old lady"]),
-    ]),
-    Single(Query(&["👮‍♀️", "👮🏻‍♀️", "👮🏼‍♀️", "👮", "👮🏻", "👮🏼", "👮‍♂️", "👮🏻‍♂️", "👮🏼‍♂️"], &["police uniform"])),
-    Single(Query(&["👩‍💻", "👩🏻‍💻", "👩🏼‍💻", "🧑‍💻", "🧑🏻‍💻", "🧑🏼‍💻", "👨‍💻", "👨🏻‍💻", "👨🏼‍💻"], &["programmer with computer"])),
-    Single(Query(&["👩‍🚒", "👩🏻‍🚒", "👩🏼‍🚒", "🧑‍🚒", "🧑🏻‍🚒", "🧑🏼‍🚒", "👨‍🚒", "👨🏻‍🚒", "👨🏼‍🚒"], &["fireman at work"])),
-    Single(Query(&["👩‍🚀", "👩🏻‍🚀", "👩🏼‍🚀", "🧑‍🚀", "🧑🏻‍🚀", "🧑🏼‍🚀", "👨‍🚀", "👨🏻‍🚀", "👨🏼‍🚀"], &["spaceman"])),
-    Single(Query(&["👰‍♀️", "👰🏻‍♀️", "👰🏼‍♀️", "👰", "👰🏻", "👰🏼"], &["wife in wedding dress"])),
-    Single(Query(&["🤴", "🤴🏻", "🤴🏼", "👑"], &["king with a crown", "crown"])),
-    Single(Query(&["🎅", "🎅🏻", "🎅🏼"], &["santa clause"])),
-    Single(Query(&["🤦‍♂️", "🤦🏻‍♂️", "🤦🏼‍♂️", "🤦", "🤦🏻", "🤦🏼", "🤦‍♀️", "🤦🏻‍♀️", "🤦🏼‍♀️"], &["facepalm"])),
-    Single(Query(&["🤷", "🤷🏻", "🤷🏼", "🤷‍♀️", "🤷🏻‍♀️", "🤷🏼‍♀️", "🤷‍♂️", "🤷🏻‍♂️", "🤷🏼‍♂️"], &["shrugging hands"])),
-];
+/// Whether the emoji is used by any query of the catalog.
+pub fn has_emoji(emoji: &str) -> bool {
+    GROUPS
+        .read()
+        .unwrap()
+        .iter()
+        .any(|group| group.has_emoji(emoji))
+}
+
+#[derive(Debug)]
+pub enum CatalogError {
+    Parse(serde_json::Error),
+    TooFewGroups(usize),
+    EmptyGroup(usize),
+    EmptyEmojis(usize),
+    EmptyPhrases(usize),
+    EmptyEmoji(usize),
+    EmptyPhrase(usize),
+    DuplicateEmoji(String),
+    DuplicatePhrase(String),
+}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use CatalogError as Error;
+        match self {
+            Error::Parse(error) => write!(f, "failed to parse catalog: {error}"),
+            Error::TooFewGroups(len) => {
+                write!(
+                    f,
+                    "catalog has {len} groups, at least {MIN_GROUPS} required"
+                )
+            }
+            Error::EmptyGroup(idx) => write!(f, "group #{idx} has no queries"),
+            Error::EmptyEmojis(idx) => write!(f, "group #{idx} has a query without emojis"),
+            Error::EmptyPhrases(idx) => write!(f, "group #{idx} has a query without phrases"),
+            Error::EmptyEmoji(idx) => write!(f, "group #{idx} has an empty emoji"),
+            Error::EmptyPhrase(idx) => write!(f, "group #{idx} has an empty phrase"),
+            Error::DuplicateEmoji(emoji) => {
+                write!(f, "emoji \"{emoji}\" is used in more than one group")
+            }
+            Error::DuplicatePhrase(phrase) => {
+                write!(f, "phrase \"{phrase}\" is used in more than one query")
+            }
+        }
+    }
+}
+
+impl error::Error for CatalogError {}
+
+fn parse(source: &str) -> Result<Vec<Group>, CatalogError> {
+    let groups: Vec<Group> = serde_json::from_str(source).map_err(CatalogError::Parse)?;
+
+    if groups.len() < MIN_GROUPS {
+        return Err(CatalogError::TooFewGroups(groups.len()));
+    }
+
+    let mut emojis: HashMap<String, usize> = HashMap::new();
+    let mut phrases = HashSet::new();
+    for (idx, group) in groups.iter().enumerate() {
+        let queries = group.queries();
+        if queries.is_empty() {
+            return Err(CatalogError::EmptyGroup(idx));
+        }
+
+        for query in queries {
+            if query.emojis.is_empty() {
+                return Err(CatalogError::EmptyEmojis(idx));
+            }
+            if query.phrases.is_empty() {
+                return Err(CatalogError::EmptyPhrases(idx));
+            }
+            // Same picture for different emojis makes the answer ambiguous.
+            for phrase in &query.phrases {
+                if phrase.trim().is_empty() {
+                    return Err(CatalogError::EmptyPhrase(idx));
+                }
+                if !phrases.insert(normalize_phrase(phrase)) {
+                    return Err(CatalogError::DuplicatePhrase(phrase.clone()));
+                }
+            }
+
+            for emoji in &query.emojis {
+                if emoji.trim().is_empty() {
+                    return Err(CatalogError::EmptyEmoji(idx));
+                }
+                if let Some(other_idx) = emojis.insert(normalize_emoji(emoji), idx) {
+                    if other_idx != idx {
+                        return Err(CatalogError::DuplicateEmoji(emoji.clone()));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(groups)
+}
+
+/// Loads catalog from a JSON file, or the embedded one if `path` is `None`.
+/// Returns number of loaded groups.
+pub async fn load(path: Option<&Path>) -> Result<usize, anyhow::Error> {
+    let groups = if let Some(path) = path {
+        let source = fs::read_to_string(path).await?;
+        parse(&source)?
+    } else {
+        parse(DEFAULT_CATALOG)?
+    };
+    let len = groups.len();
+    *GROUPS.write().unwrap() = groups;
+
+    Ok(len)
+}
+
+/// Reloads catalog from `path` on every SIGHUP, keeps the current one if the new is invalid.
+pub async fn reload_on_hangup(path: PathBuf) {
+    let mut hangup = signal(SignalKind::hangup()).expect("Couldn't listen for SIGHUP");
+
+    while hangup.recv().await.is_some() {
+        match load(Some(&path)).await {
+            Ok(len) => log::info!("Reloaded {len} groups from {}", path.display()),
+            Err(error) => log::error!("Couldn't reload {}: {error}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_groups() -> Vec<Group> {
+        parse(DEFAULT_CATALOG).unwrap()
+    }
+
+    fn custom_queries() -> Vec<CustomQuery> {
+        let questions = [
+            ("🦀", Picture::Phrase("crab on a beach".into())),
+            ("🦞", Picture::Phrase("Crab on a beach ".into())),
+            ("🦀\u{fe0f}", Picture::File("file-1".into())),
+            ("🧲", Picture::File("file-2".into())),
+            ("🦑", Picture::File("file-2".into())),
+            ("🪼", Picture::Phrase("jellyfish".into())),
+            ("🦭", Picture::Phrase("seal".into())),
+        ];
+        questions
+            .into_iter()
+            .enumerate()
+            .map(|(id, (emoji, picture))| CustomQuery {
+                id: id as i64,
+                emoji: emoji.into(),
+                picture,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pick_variants_can_be_told_apart() {
+        let groups = default_groups();
+        let custom = custom_queries();
+        for amount in 2..=MAX_CAPTCHA_OPTIONS as usize {
+            for custom in [&custom[..0], &custom[..]] {
+                for _ in 0..300 {
+                    let variants = Combination::pick_variants(&groups, amount, custom).unwrap();
+                    assert_eq!(variants.len(), amount);
+
+                    for (emoji, picture) in &variants {
+                        assert!(!emoji.trim().is_empty());
+                        assert!(!picture.key().trim().is_empty());
+                    }
+
+                    let emojis: HashSet<_> =
+                        variants.iter().map(|(e, _)| normalize_emoji(e)).collect();
+                    assert_eq!(emojis.len(), amount, "{variants:?}");
+
+                    let keys: HashSet<_> = variants.iter().map(|(_, p)| p.key()).collect();
+                    assert_eq!(keys.len(), amount, "{variants:?}");
+
+                    let custom_emojis = variants
+                        .iter()
+                        .filter(|(emoji, _)| {
+                            custom.iter().any(|query| same_emoji(&query.emoji, emoji))
+                        })
+                        .count();
+                    assert!(custom_emojis <= amount / 2);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pick_answer_is_among_variants() {
+        let groups = default_groups();
+        let custom = custom_queries();
+        for amount in 2..=MAX_CAPTCHA_OPTIONS as usize {
+            for _ in 0..100 {
+                let comb = Combination::pick_from(&groups, amount, &custom).unwrap();
+                assert_eq!(comb.emojis.len(), amount);
+                assert!(comb.emojis.contains(&comb.answer));
+            }
+        }
+    }
+
+    #[test]
+    fn pick_custom_pictures_are_unique() {
+        let groups = default_groups();
+        let custom = custom_queries();
+        for _ in 0..100 {
+            let mut variants = Variants::default();
+            for query in &custom {
+                variants.add(&query.emoji, &query.picture);
+            }
+            let keys: HashSet<_> = variants.list.iter().map(|(_, p)| p.key()).collect();
+            assert_eq!(keys.len(), variants.list.len());
+            // Crab emoji and picture, magnet file and jellyfish and seal phrases.
+            assert_eq!(variants.list.len(), 4);
+
+            let comb = Combination::pick_from(&groups, 6, &custom).unwrap();
+            let answer = custom.iter().find(|query| query.emoji == comb.answer);
+            if let Some(query) = answer {
+                assert_eq!(query.picture.key(), comb.picture.key());
+            }
+        }
+    }
+
+    #[test]
+    fn pick_fails_without_enough_variants() {
+        let custom = custom_queries();
+        let error = Combination::pick_from(&[], 6, &custom).err().unwrap();
+        assert_eq!(error.found, 3);
+        assert_eq!(error.required, 6);
+        assert!(Combination::pick_from(&[], 2, &[]).is_err());
+    }
+}
@@ -1,7 +1,10 @@
+use crate::images::ImageProvider;
+use futures::future::BoxFuture;
 use rand::{seq::SliceRandom, thread_rng};
 use reqwest::{header, Client, Url};
 use serde_json::Value;
 use std::fmt;
+use teloxide::types::InputFile;
 
 const SEARCH_URL: &str = "https://api.qwant.com/v3/search/images";
 const PARAMS: [(&str, &str); 6] = [
@@ -40,8 +43,8 @@ pub async fn get_image_url(query: &str) -> Result<Url, anyhow::Error> {
         .await?;
     if let Value::Array(items) = &data["data"]["result"]["items"] {
         let mut rng = thread_rng();
-        if let Value::Object(map) = items.choose(&mut rng).unwrap() {
-            if let Value::String(url) = map.get("thumbnail").unwrap() {
+        if let Some(Value::Object(map)) = items.choose(&mut rng) {
+            if let Some(Value::String(url)) = map.get("thumbnail") {
                 return Ok(Url::parse(url)?);
             }
         }
@@ -49,3 +52,19 @@ pub async fn get_image_url(query: &str) -> Result<Url, anyhow::Error> {
 
     Err(QwantResponseError.into())
 }
+
+pub struct Qwant;
+
+impl ImageProvider for Qwant {
+    fn name(&self) -> &'static str {
+        "qwant"
+    }
+
+    fn get_image<'a>(&'a self, query: &'a str) -> BoxFuture<'a, Result<InputFile, anyhow::Error>> {
+        Box::pin(async move {
+            let url = get_image_url(query).await?;
+            log::info!("Image URL: {url}");
+            Ok(InputFile::url(url))
+        })
+    }
+}
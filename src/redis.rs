@@ -1,11 +1,33 @@
+use crate::captcha::CaptchaKind;
 use redis::{aio::ConnectionManager, AsyncCommands, RedisResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::OnceLock;
 use std::time::{Duration, SystemTime};
-use teloxide::types::{ChatId, UserId};
+use teloxide::types::{ChatId, ChatPermissions, Message, MessageId, UserId};
 use tokio::sync::OnceCell;
 
 const ANSWER_PREFIX: &str = "answer";
 const IGNORE_KEY: &str = "ignore";
+const JOINED_PREFIX: &str = "joined";
+/// Both "User joined" message and `chat_member` update arrive within this time.
+const JOINED_EXPIRE: u64 = 60;
+const FILE_IDS_PREFIX: &str = "file_ids";
+/// Cached file_ids of a query phrase are dropped when it's not used for CAPTCHAs for this time.
+const FILE_IDS_EXPIRE: u64 = 30 * 24 * 60 * 60;
+const JOINS_PREFIX: &str = "joins";
+const USER_CAPTCHAS_PREFIX: &str = "user_captchas";
+const LOCKDOWN_PREFIX: &str = "lockdown";
+const LOCKDOWNS_KEY: &str = "lockdowns";
+const PROBATION_PREFIX: &str = "probation";
+/// Probation ends after a while even if the user didn't post enough messages.
+const PROBATION_EXPIRE: u64 = 7 * 24 * 60 * 60;
+const CAPTCHAS_KEY: &str = "captchas";
+const CAPTCHAS_DATA_KEY: &str = "captchas_data";
+const DIALOGUE_PREFIX: &str = "dialogue";
 static REDIS: OnceCell<ConnectionManager> = OnceCell::const_new();
+static START_LOCKDOWN_SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+static COUNT_PROBATION_SCRIPT: OnceLock<redis::Script> = OnceLock::new();
 
 pub async fn setup(url: url::Url) -> RedisResult<()> {
     let cm = ::redis::Client::open(url)
@@ -20,10 +42,18 @@ pub async fn setup(url: url::Url) -> RedisResult<()> {
     Ok(())
 }
 
+/// Pending CAPTCHA of a user.
+pub struct Answer {
+    pub answer: String,
+    pub attempts: u8,
+    pub message_id: MessageId,
+    pub kind: CaptchaKind,
+}
+
 pub async fn set_answer(
     chat_id: ChatId,
     user_id: UserId,
-    answer: &str,
+    answer: &Answer,
     captcha_expire: u64,
     ignore_expire: u64,
 ) -> RedisResult<()> {
@@ -38,34 +68,467 @@ pub async fn set_answer(
     let mut cm = REDIS.get().unwrap().clone();
     redis::pipe()
         .atomic()
-        .set(&key, answer)
+        .del(&key)
+        .hset_multiple(
+            &key,
+            &[
+                ("answer", answer.answer.clone()),
+                ("attempts", answer.attempts.to_string()),
+                ("message_id", answer.message_id.0.to_string()),
+                ("kind", answer.kind.to_string()),
+            ],
+        )
         .expire(&key, captcha_expire as i64)
         .zadd(IGNORE_KEY, member, epoch)
-        .query_async(&mut cm)
+        .query_async::<_, ()>(&mut cm)
         .await?;
 
     Ok(())
 }
 
-pub async fn get_answer(chat_id: ChatId, user_id: UserId) -> RedisResult<Option<String>> {
+pub async fn get_answer(chat_id: ChatId, user_id: UserId) -> RedisResult<Option<Answer>> {
     let key = format!("{ANSWER_PREFIX}:{chat_id}:{user_id}");
     let mut cm = REDIS.get().unwrap().clone();
-    let answer = cm.get(key).await?;
+    let fields: HashMap<String, String> = cm.hgetall(key).await?;
+    let answer = match (
+        fields.get("answer"),
+        fields.get("attempts").and_then(|val| val.parse().ok()),
+        fields.get("message_id").and_then(|val| val.parse().ok()),
+        fields.get("kind").and_then(|val| val.parse().ok()),
+    ) {
+        (Some(answer), Some(attempts), Some(message_id), Some(kind)) => Some(Answer {
+            answer: answer.clone(),
+            attempts,
+            message_id: MessageId(message_id),
+            kind,
+        }),
+        _ => None,
+    };
     Ok(answer)
 }
 
+/// Replaces the answer after an incorrect attempt, keeping CAPTCHA expiration.
+pub async fn retry_answer(
+    chat_id: ChatId,
+    user_id: UserId,
+    answer: &str,
+    message_id: MessageId,
+) -> RedisResult<()> {
+    let key = format!("{ANSWER_PREFIX}:{chat_id}:{user_id}");
+    let mut cm = REDIS.get().unwrap().clone();
+    redis::pipe()
+        .atomic()
+        .hset(&key, "answer", answer)
+        .hset(&key, "message_id", message_id.0)
+        .hincr(&key, "attempts", -1)
+        .query_async::<_, ()>(&mut cm)
+        .await?;
+    Ok(())
+}
+
+/// Sets the answer to CAPTCHA shown in another message, keeping CAPTCHA expiration and attempts.
+pub async fn update_answer(
+    chat_id: ChatId,
+    user_id: UserId,
+    answer: &str,
+    message_id: MessageId,
+) -> RedisResult<()> {
+    let key = format!("{ANSWER_PREFIX}:{chat_id}:{user_id}");
+    let mut cm = REDIS.get().unwrap().clone();
+    cm.hset_multiple::<_, _, _, ()>(
+        key,
+        &[
+            ("answer", answer.to_owned()),
+            ("message_id", message_id.0.to_string()),
+        ],
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_answer(chat_id: ChatId, user_id: UserId) -> RedisResult<()> {
+    let key = format!("{ANSWER_PREFIX}:{chat_id}:{user_id}");
+    let mut cm = REDIS.get().unwrap().clone();
+    cm.del::<_, ()>(key).await?;
+    Ok(())
+}
+
+/// Marks user as joined the chat, returns `false` if the user was already marked recently.
+pub async fn mark_joined(chat_id: ChatId, user_id: UserId) -> RedisResult<bool> {
+    let key = format!("{JOINED_PREFIX}:{chat_id}:{user_id}");
+    let mut cm = REDIS.get().unwrap().clone();
+    let marked: Option<String> = redis::cmd("SET")
+        .arg(key)
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(JOINED_EXPIRE)
+        .query_async(&mut cm)
+        .await?;
+    Ok(marked.is_some())
+}
+
+/// Forgets that user joined the chat, so rejoining shows CAPTCHA again.
+pub async fn unmark_joined(chat_id: ChatId, user_id: UserId) -> RedisResult<()> {
+    let key = format!("{JOINED_PREFIX}:{chat_id}:{user_id}");
+    let mut cm = REDIS.get().unwrap().clone();
+    cm.del::<_, ()>(key).await?;
+    Ok(())
+}
+
+/// Counts hits of the `key` within the current minute.
+async fn count_per_minute(key: &str) -> RedisResult<u64> {
+    let minute = epoch_secs(SystemTime::now()) / 60;
+    let key = format!("{key}:{minute}");
+    let mut cm = REDIS.get().unwrap().clone();
+    let (count,): (u64,) = redis::pipe()
+        .atomic()
+        .incr(&key, 1)
+        .expire(&key, 120)
+        .ignore()
+        .query_async(&mut cm)
+        .await?;
+    Ok(count)
+}
+
+/// Counts users joined the chat within the current minute.
+pub async fn count_join(chat_id: ChatId) -> RedisResult<u64> {
+    count_per_minute(&format!("{JOINS_PREFIX}:{chat_id}")).await
+}
+
+/// Counts CAPTCHAs generated for the user within the current minute in any chat.
+pub async fn count_user_captcha(user_id: UserId) -> RedisResult<u64> {
+    count_per_minute(&format!("{USER_CAPTCHAS_PREFIX}:{user_id}")).await
+}
+
 pub async fn is_ignored(chat_id: ChatId, user_id: UserId) -> bool {
     let member = format!("{chat_id}:{user_id}");
     let mut cm = REDIS.get().unwrap().clone();
     let result: RedisResult<Option<f64>> = cm.zscore(IGNORE_KEY, member).await;
-    if let Ok(epoch) = result {
-        if let Some(epoch) = epoch {
-            let now = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs_f64();
-            return now < epoch;
-        }
+    if let Ok(Some(epoch)) = result {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64();
+        return now < epoch;
     }
     false
 }
+
+/// Adds file_id to the query's cache, the oldest ones are dropped beyond `limit`.
+pub async fn add_file_id(query: &str, file_id: &str, limit: usize) -> RedisResult<()> {
+    let key = format!("{FILE_IDS_PREFIX}:{query}");
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64();
+    let mut cm = REDIS.get().unwrap().clone();
+    ::redis::pipe()
+        .atomic()
+        .zadd(&key, file_id, now)
+        .zremrangebyrank(&key, 0, -(limit as isize) - 1)
+        .expire(&key, FILE_IDS_EXPIRE as i64)
+        .query_async::<_, ()>(&mut cm)
+        .await?;
+    Ok(())
+}
+
+/// Returns random cached file_id for the query if there are at least `min_size` of them.
+pub async fn random_file_id(query: &str, min_size: usize) -> RedisResult<Option<String>> {
+    let key = format!("{FILE_IDS_PREFIX}:{query}");
+    let mut cm = REDIS.get().unwrap().clone();
+    let size: usize = cm.zcard(&key).await?;
+    if size == 0 || size < min_size {
+        return Ok(None);
+    }
+    let index = rand::random::<usize>() % size;
+    let file_ids: Vec<String> = cm.zrange(key, index as isize, index as isize).await?;
+    Ok(file_ids.into_iter().next())
+}
+
+/// CAPTCHA waiting for an answer until its deadline.
+#[derive(Serialize, Deserialize)]
+pub struct PendingCaptcha {
+    pub chat_id: ChatId,
+    pub user_id: UserId,
+    /// Chat with the CAPTCHA message, differs from `chat_id` when CAPTCHA is sent in private.
+    pub message_chat_id: ChatId,
+    pub message_id: MessageId,
+    pub kind: CaptchaKind,
+    pub user_tag: String,
+    /// CAPTCHA shown in private chat to a user who followed the link from the group notice.
+    #[serde(default)]
+    pub private_message_id: Option<MessageId>,
+}
+
+fn epoch_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+pub async fn add_pending_captcha(
+    captcha: &PendingCaptcha,
+    deadline: SystemTime,
+) -> RedisResult<()> {
+    let member = format!("{}:{}", captcha.chat_id, captcha.user_id);
+    let data = serde_json::to_string(captcha).unwrap();
+    let mut cm = REDIS.get().unwrap().clone();
+    redis::pipe()
+        .atomic()
+        .zadd(CAPTCHAS_KEY, &member, epoch_secs(deadline))
+        .hset(CAPTCHAS_DATA_KEY, &member, data)
+        .query_async::<_, ()>(&mut cm)
+        .await?;
+    Ok(())
+}
+
+/// Removes and returns pending CAPTCHA of the user, `None` if it has already expired.
+pub async fn take_pending_captcha(
+    chat_id: ChatId,
+    user_id: UserId,
+) -> RedisResult<Option<PendingCaptcha>> {
+    let member = format!("{chat_id}:{user_id}");
+    let mut cm = REDIS.get().unwrap().clone();
+    let (removed, data): (usize, Option<String>) = redis::pipe()
+        .atomic()
+        .zrem(CAPTCHAS_KEY, &member)
+        .hget(CAPTCHAS_DATA_KEY, &member)
+        .hdel(CAPTCHAS_DATA_KEY, &member)
+        .ignore()
+        .query_async(&mut cm)
+        .await?;
+
+    if removed == 0 {
+        return Ok(None);
+    }
+    Ok(data.and_then(|data| serde_json::from_str(&data).ok()))
+}
+
+/// Points pending CAPTCHA shown in `message` to the message which replaced it, keeping the deadline.
+pub async fn move_pending_captcha(
+    chat_id: ChatId,
+    user_id: UserId,
+    message: &Message,
+    message_id: MessageId,
+) -> RedisResult<()> {
+    update_pending_captcha(chat_id, user_id, |captcha| {
+        if captcha.message_chat_id == message.chat.id && captcha.message_id == message.id {
+            captcha.message_id = message_id;
+        } else if message.chat.is_private() && captcha.private_message_id == Some(message.id) {
+            captcha.private_message_id = Some(message_id);
+        }
+    })
+    .await
+}
+
+/// Remembers CAPTCHA shown in private chat, so it's deleted along with the group notice.
+pub async fn set_private_captcha(
+    chat_id: ChatId,
+    user_id: UserId,
+    message_id: MessageId,
+) -> RedisResult<()> {
+    update_pending_captcha(chat_id, user_id, |captcha| {
+        captcha.private_message_id = Some(message_id);
+    })
+    .await
+}
+
+async fn update_pending_captcha(
+    chat_id: ChatId,
+    user_id: UserId,
+    update: impl FnOnce(&mut PendingCaptcha),
+) -> RedisResult<()> {
+    let member = format!("{chat_id}:{user_id}");
+    let mut cm = REDIS.get().unwrap().clone();
+    let (deadline, data): (Option<f64>, Option<String>) = redis::pipe()
+        .zscore(CAPTCHAS_KEY, &member)
+        .hget(CAPTCHAS_DATA_KEY, &member)
+        .query_async(&mut cm)
+        .await?;
+
+    let captcha = data.and_then(|data| serde_json::from_str::<PendingCaptcha>(&data).ok());
+    if let (Some(_), Some(mut captcha)) = (deadline, captcha) {
+        update(&mut captcha);
+        let data = serde_json::to_string(&captcha).unwrap();
+        cm.hset::<_, _, _, ()>(CAPTCHAS_DATA_KEY, member, data)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Removes and returns CAPTCHAs with deadline before `now`.
+/// A CAPTCHA is returned only by the call which actually removed it,
+/// so it's safe to be called from several bot instances.
+pub async fn take_expired_captchas(now: SystemTime) -> RedisResult<Vec<PendingCaptcha>> {
+    let mut cm = REDIS.get().unwrap().clone();
+    let members: Vec<String> = cm
+        .zrangebyscore(CAPTCHAS_KEY, "-inf", epoch_secs(now))
+        .await?;
+
+    let mut captchas = Vec::with_capacity(members.len());
+    for member in members {
+        let (removed, data): (usize, Option<String>) = redis::pipe()
+            .atomic()
+            .zrem(CAPTCHAS_KEY, &member)
+            .hget(CAPTCHAS_DATA_KEY, &member)
+            .hdel(CAPTCHAS_DATA_KEY, &member)
+            .ignore()
+            .query_async(&mut cm)
+            .await?;
+
+        if removed == 0 {
+            continue;
+        }
+        match data.map(|data| serde_json::from_str(&data)) {
+            Some(Ok(captcha)) => captchas.push(captcha),
+            Some(Err(error)) => log::error!("Invalid pending captcha {member}: {error}"),
+            None => log::error!("No data for pending captcha {member}"),
+        }
+    }
+
+    Ok(captchas)
+}
+
+/// Saves chat `permissions` to restore them at the deadline, returns `false` if already locked down.
+pub async fn start_lockdown(
+    chat_id: ChatId,
+    permissions: &ChatPermissions,
+    deadline: SystemTime,
+) -> RedisResult<bool> {
+    // Permissions and the deadline are saved together, so lockdown can't be left without either.
+    let script = START_LOCKDOWN_SCRIPT.get_or_init(|| {
+        redis::Script::new(
+            r"
+if not redis.call('SET', KEYS[1], ARGV[1], 'NX') then
+    return 0
+end
+redis.call('ZADD', KEYS[2], ARGV[2], ARGV[3])
+return 1
+            ",
+        )
+    });
+    let key = format!("{LOCKDOWN_PREFIX}:{chat_id}");
+    let data = serde_json::to_string(permissions).unwrap();
+    let deadline = epoch_secs(deadline);
+    let mut cm = REDIS.get().unwrap().clone();
+    let started: bool = script
+        .key(key)
+        .key(LOCKDOWNS_KEY)
+        .arg(data)
+        .arg(deadline)
+        .arg(chat_id.0)
+        .invoke_async(&mut cm)
+        .await?;
+    Ok(started)
+}
+
+pub async fn get_lockdown(chat_id: ChatId) -> RedisResult<Option<ChatPermissions>> {
+    let key = format!("{LOCKDOWN_PREFIX}:{chat_id}");
+    let mut cm = REDIS.get().unwrap().clone();
+    let data: Option<String> = cm.get(key).await?;
+    Ok(data.and_then(|data| serde_json::from_str(&data).ok()))
+}
+
+/// Finishes lockdown, should be called after permissions are restored.
+pub async fn end_lockdown(chat_id: ChatId) -> RedisResult<()> {
+    let key = format!("{LOCKDOWN_PREFIX}:{chat_id}");
+    let mut cm = REDIS.get().unwrap().clone();
+    redis::pipe()
+        .atomic()
+        .del(key)
+        .ignore()
+        .zrem(LOCKDOWNS_KEY, chat_id.0)
+        .ignore()
+        .query_async::<_, ()>(&mut cm)
+        .await
+}
+
+/// Sets a new deadline of a lockdown, e.g. to retry lifting it.
+pub async fn delay_lockdown(chat_id: ChatId, deadline: SystemTime) -> RedisResult<()> {
+    let mut cm = REDIS.get().unwrap().clone();
+    cm.zadd::<_, _, _, ()>(LOCKDOWNS_KEY, chat_id.0, epoch_secs(deadline))
+        .await
+}
+
+/// Returns chats with lockdown deadline before `now`, each chat is returned only once.
+pub async fn take_expired_lockdowns(now: SystemTime) -> RedisResult<Vec<ChatId>> {
+    let mut cm = REDIS.get().unwrap().clone();
+    let chat_ids: Vec<i64> = cm
+        .zrangebyscore(LOCKDOWNS_KEY, "-inf", epoch_secs(now))
+        .await?;
+
+    let mut expired = Vec::with_capacity(chat_ids.len());
+    for chat_id in chat_ids {
+        let removed: usize = cm.zrem(LOCKDOWNS_KEY, chat_id).await?;
+        if removed > 0 {
+            expired.push(ChatId(chat_id));
+        }
+    }
+
+    Ok(expired)
+}
+
+/// Starts checking the next `messages` of a user who passed CAPTCHA.
+pub async fn start_probation(chat_id: ChatId, user_id: UserId, messages: u8) -> RedisResult<()> {
+    let key = format!("{PROBATION_PREFIX}:{chat_id}:{user_id}");
+    let mut cm = REDIS.get().unwrap().clone();
+    cm.set_ex::<_, _, ()>(key, messages, PROBATION_EXPIRE)
+        .await?;
+    Ok(())
+}
+
+/// Counts a message of the user, returns `false` if the user isn't on probation.
+pub async fn count_probation_message(chat_id: ChatId, user_id: UserId) -> RedisResult<bool> {
+    let key = format!("{PROBATION_PREFIX}:{chat_id}:{user_id}");
+    let mut cm = REDIS.get().unwrap().clone();
+    // Almost all messages are sent by users who aren't on probation.
+    if !cm.exists(&key).await? {
+        return Ok(false);
+    }
+
+    let script = COUNT_PROBATION_SCRIPT.get_or_init(|| {
+        redis::Script::new(
+            r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return 0
+end
+if redis.call('DECR', KEYS[1]) <= 0 then
+    redis.call('DEL', KEYS[1])
+end
+return 1
+            ",
+        )
+    });
+    let counted: bool = script.key(key).invoke_async(&mut cm).await?;
+    Ok(counted)
+}
+
+pub async fn end_probation(chat_id: ChatId, user_id: UserId) -> RedisResult<()> {
+    let key = format!("{PROBATION_PREFIX}:{chat_id}:{user_id}");
+    let mut cm = REDIS.get().unwrap().clone();
+    cm.del::<_, ()>(key).await?;
+    Ok(())
+}
+
+pub async fn get_dialogue(chat_id: ChatId) -> RedisResult<Option<String>> {
+    let key = format!("{DIALOGUE_PREFIX}:{chat_id}");
+    let mut cm = REDIS.get().unwrap().clone();
+    cm.get(key).await
+}
+
+/// Saves serialized dialogue state, it expires after `ttl` unless updated.
+pub async fn set_dialogue(chat_id: ChatId, dialogue: String, ttl: Duration) -> RedisResult<()> {
+    let key = format!("{DIALOGUE_PREFIX}:{chat_id}");
+    let mut cm = REDIS.get().unwrap().clone();
+    cm.set_ex::<_, _, ()>(key, dialogue, ttl.as_secs()).await?;
+    Ok(())
+}
+
+pub async fn delete_dialogue(chat_id: ChatId) -> RedisResult<()> {
+    let key = format!("{DIALOGUE_PREFIX}:{chat_id}");
+    let mut cm = REDIS.get().unwrap().clone();
+    cm.del::<_, ()>(key).await?;
+    Ok(())
+}
@@ -0,0 +1,178 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::f32::consts::PI;
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+/// Size in pixels of a glyph dot.
+const SCALE: usize = 8;
+const PADDING: usize = 24;
+const NOISE_LINES: usize = 6;
+const NOISE_DOTS: usize = 600;
+
+type Rgb = [u8; 3];
+
+/// Bitmap font with rows of 5 dots, the most significant bit is the leftmost dot.
+#[rustfmt::skip]
+fn glyph(ch: char) -> Option<[u8; GLYPH_HEIGHT]> {
+    let rows = match ch {
+        '0' => [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+        '1' => [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        '2' => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+        '3' => [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110],
+        '4' => [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+        '5' => [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+        '6' => [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+        '7' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+        '8' => [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+        '9' => [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+        '+' => [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000],
+        '-' => [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000],
+        '=' => [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000],
+        '?' => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100],
+        ' ' => [0; GLYPH_HEIGHT],
+        _ => return None,
+    };
+    Some(rows)
+}
+
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize, background: Rgb) -> Self {
+        Self {
+            width,
+            height,
+            pixels: background.repeat(width * height),
+        }
+    }
+
+    /// Sets color of the square with top left corner at `x`, `y`, parts outside the canvas are skipped.
+    fn fill(&mut self, x: i64, y: i64, size: usize, color: Rgb) {
+        for y in y..y + size as i64 {
+            for x in x..x + size as i64 {
+                if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+                    continue;
+                }
+                let idx = (y as usize * self.width + x as usize) * 3;
+                self.pixels[idx..idx + 3].copy_from_slice(&color);
+            }
+        }
+    }
+
+    fn line(&mut self, (x0, y0): (i64, i64), (x1, y1): (i64, i64), size: usize, color: Rgb) {
+        let steps = (x1 - x0).abs().max((y1 - y0).abs()).max(1);
+        for step in 0..=steps {
+            let x = x0 + (x1 - x0) * step / steps;
+            let y = y0 + (y1 - y0) * step / steps;
+            self.fill(x, y, size, color);
+        }
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, png::EncodingError> {
+        let mut data = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut data, self.width as u32, self.height as u32);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(&self.pixels)?;
+            writer.finish()?;
+        }
+        Ok(data)
+    }
+}
+
+fn random_color(rng: &mut StdRng, min: u8, max: u8) -> Rgb {
+    [
+        rng.gen_range(min..=max),
+        rng.gen_range(min..=max),
+        rng.gen_range(min..=max),
+    ]
+}
+
+/// Renders `text` as PNG with distorted glyphs and noise, the same `seed` gives the same picture.
+/// Characters missing in the font are skipped.
+pub fn render(text: &str, seed: u64) -> Result<Vec<u8>, png::EncodingError> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let glyphs: Vec<_> = text.chars().filter_map(glyph).collect();
+    let advance = (GLYPH_WIDTH + 1) * SCALE;
+    let width = glyphs.len().max(1) * advance + 2 * PADDING;
+    let height = GLYPH_HEIGHT * SCALE + 2 * PADDING;
+    let mut canvas = Canvas::new(width, height, random_color(&mut rng, 220, 255));
+
+    // Wave shifts dots of glyphs vertically depending on their horizontal position.
+    let amplitude = rng.gen_range(3.0..6.0_f32);
+    let period = rng.gen_range(60.0..120.0_f32);
+    let phase = rng.gen_range(0.0..2.0 * PI);
+
+    for (idx, rows) in glyphs.iter().enumerate() {
+        let color = random_color(&mut rng, 0, 120);
+        let x0 = (PADDING + idx * advance) as i64 + rng.gen_range(-3..=3);
+        let y0 = PADDING as i64 + rng.gen_range(-6..=6);
+        let shear = rng.gen_range(-0.3..0.3_f32);
+
+        for (gy, row) in rows.iter().enumerate() {
+            for gx in 0..GLYPH_WIDTH {
+                if row >> (GLYPH_WIDTH - 1 - gx) & 1 == 0 {
+                    continue;
+                }
+                let lift = ((GLYPH_HEIGHT - gy) * SCALE) as f32;
+                let x = x0 + (gx * SCALE) as i64 + (shear * lift) as i64;
+                let wave = amplitude * (x as f32 / period * 2.0 * PI + phase).sin();
+                let y = y0 + (gy * SCALE) as i64 + wave as i64;
+                canvas.fill(x, y, SCALE, color);
+            }
+        }
+    }
+
+    for _ in 0..NOISE_LINES {
+        let color = random_color(&mut rng, 60, 180);
+        let from = (0, rng.gen_range(0..height as i64));
+        let to = (width as i64, rng.gen_range(0..height as i64));
+        canvas.line(from, to, 2, color);
+    }
+
+    for _ in 0..NOISE_DOTS {
+        let color = random_color(&mut rng, 0, 255);
+        let x = rng.gen_range(0..width as i64);
+        let y = rng.gen_range(0..height as i64);
+        canvas.fill(x, y, 2, color);
+    }
+
+    canvas.encode()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::Example;
+
+    #[test]
+    fn same_seed_same_picture() {
+        let png = render("3 + 4 = ?", 42).unwrap();
+        assert_eq!(png, render("3 + 4 = ?", 42).unwrap());
+        assert_ne!(png, render("3 + 4 = ?", 43).unwrap());
+    }
+
+    #[test]
+    fn picture_fits_text() {
+        let png = render("12 - 5 = ?", 7).unwrap();
+        let decoder = png::Decoder::new(png.as_slice());
+        let info = decoder.read_info().unwrap().info().clone();
+        let advance = (GLYPH_WIDTH + 1) * SCALE;
+        assert_eq!(info.width as usize, 10 * advance + 2 * PADDING);
+        assert_eq!(info.height as usize, GLYPH_HEIGHT * SCALE + 2 * PADDING);
+    }
+
+    #[test]
+    fn font_has_example_glyphs() {
+        for _ in 0..100 {
+            let example = Example::pick(6);
+            assert!(example.question.chars().all(|ch| glyph(ch).is_some()));
+        }
+    }
+}
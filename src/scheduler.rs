@@ -0,0 +1,93 @@
+use crate::captcha::CaptchaKind;
+use crate::redis::{self, PendingCaptcha};
+use crate::{lockdown, settings, t, utils};
+use ::redis::RedisResult;
+use std::time::{Duration, SystemTime};
+use teloxide::prelude::*;
+use tokio::time::interval;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Persists CAPTCHA so it expires at the deadline even if the bot was restarted meanwhile.
+pub async fn schedule_captcha(captcha: &PendingCaptcha, timeout: Duration) -> RedisResult<()> {
+    redis::add_pending_captcha(captcha, SystemTime::now() + timeout).await
+}
+
+/// Periodically expires overdue CAPTCHAs and lockdowns,
+/// including ones which became overdue while the bot was down.
+pub fn start(bot: crate::Bot) {
+    tokio::spawn(async move {
+        let mut interval = interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            match redis::take_expired_captchas(SystemTime::now()).await {
+                Ok(captchas) => {
+                    for captcha in captchas {
+                        tokio::spawn(expire_captcha(bot.clone(), captcha));
+                    }
+                }
+                Err(error) => log::error!("Failed to get expired captchas: {error}"),
+            }
+            match redis::take_expired_lockdowns(SystemTime::now()).await {
+                Ok(chat_ids) => {
+                    for chat_id in chat_ids {
+                        tokio::spawn(lift_lockdown(bot.clone(), chat_id));
+                    }
+                }
+                Err(error) => log::error!("Failed to get expired lockdowns: {error}"),
+            }
+        }
+    });
+}
+
+async fn expire_captcha(bot: crate::Bot, captcha: PendingCaptcha) {
+    let PendingCaptcha {
+        chat_id,
+        user_id,
+        message_chat_id,
+        message_id,
+        kind,
+        user_tag,
+        private_message_id,
+    } = captcha;
+    let settings = settings::get(chat_id);
+
+    // Answered CAPTCHAs are removed from the schedule, so this one is failed
+    // even if its message was already deleted by someone else.
+    let _ = bot.delete_message(message_chat_id, message_id).await;
+    if let Some(private_message_id) = private_message_id {
+        let _ = bot.delete_message(user_id, private_message_id).await;
+    }
+
+    match kind {
+        CaptchaKind::Member => {
+            utils::apply_fail_action(&bot, chat_id, user_id, settings.fail_action).await;
+            let text = t!(
+                "captcha-time-over",
+                settings.language,
+                user_tag = user_tag,
+                duration = settings.ignore_expire.get()
+            );
+            if let Ok(message) = bot.send_message(chat_id, text).await {
+                utils::delete_message_later(&bot, chat_id, message.id, settings.message_expire());
+            }
+        }
+        CaptchaKind::JoinRequest => {
+            if let Err(error) = bot.decline_chat_join_request(chat_id, user_id).await {
+                log::error!("Failed to decline join request of {user_id} to {chat_id}: {error}");
+            }
+            let text = t!(
+                "captcha-join-time-over",
+                settings.language,
+                duration = settings.ignore_expire.get()
+            );
+            let _ = bot.send_message(message_chat_id, text).await;
+        }
+    }
+}
+
+async fn lift_lockdown(bot: crate::Bot, chat_id: ChatId) {
+    if let Err(error) = lockdown::lift(&bot, chat_id).await {
+        log::error!("Failed to lift lockdown of chat {chat_id}: {error}");
+    }
+}
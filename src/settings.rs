@@ -1,18 +1,41 @@
-use crate::l10n::Language;
-use sqlx::{sqlite::SqlitePoolOptions, Pool, Row, Sqlite};
-use std::collections::{HashMap, HashSet};
+use crate::l10n::{self, Language};
+use crate::qna::{self, CustomQuery, Picture};
+use crate::t;
+use sqlx::query::Query;
+use sqlx::sqlite::{SqliteArguments, SqlitePoolOptions};
+use sqlx::{Pool, Row, Sqlite};
+use std::collections::HashMap;
 use std::default::Default;
-use std::num::NonZeroU64;
+use std::num::{self, NonZeroU64};
 use std::sync::{Mutex, OnceLock};
 use std::time::Duration;
 use std::{error, fmt, str};
 use teloxide::types::ChatId;
 use tokio::sync::OnceCell;
+use unicode_segmentation::UnicodeSegmentation;
 
 static SETTINGS: OnceLock<Mutex<HashMap<ChatId, Settings>>> = OnceLock::new();
 static GREETINGS: OnceLock<Mutex<HashMap<ChatId, String>>> = OnceLock::new();
+static QUESTIONS: OnceLock<Mutex<HashMap<ChatId, Vec<CustomQuery>>>> = OnceLock::new();
 static SQLITE_POOL: OnceCell<Pool<Sqlite>> = OnceCell::const_new();
 
+pub const MIN_CAPTCHA_OPTIONS: u8 = 2;
+pub const MAX_CAPTCHA_OPTIONS: u8 = 12;
+pub const MAX_CAPTCHA_ROWS: u8 = 4;
+pub const MAX_CAPTCHA_ATTEMPTS: u8 = 5;
+pub const MAX_NEWCOMER_MESSAGES: u8 = 50;
+/// Telegram bans forever for less than 30 seconds or more than 366 days.
+const MIN_BAN_DURATION: u64 = 30;
+const MAX_BAN_DURATION: u64 = 366 * 24 * 60 * 60;
+/// Bots can't delete messages older than 48 hours, CAPTCHA and temporary messages must expire earlier.
+const MAX_MESSAGE_EXPIRE: u64 = 24 * 60 * 60;
+const MIN_CAPTCHA_EXPIRE: u64 = 10;
+const MAX_LOCKDOWN_DURATION: u64 = 7 * 24 * 60 * 60;
+/// Units of durations from the largest to the smallest.
+const DURATION_UNITS: [(char, u64); 4] = [('d', 86400), ('h', 3600), ('m', 60), ('s', 1)];
+/// Telegram shows at most 8 inline keyboard buttons in a row.
+const MAX_ROW_SIZE: u8 = 8;
+
 #[derive(Debug, Clone)]
 pub struct Settings {
     pub language: Language,
@@ -21,6 +44,28 @@ pub struct Settings {
     pub message_expire: NonZeroU64,
     pub ignore_expire: NonZeroU64,
     pub delete_entry_messages: bool,
+    pub captcha_options: u8,
+    pub captcha_rows: u8,
+    pub captcha_attempts: u8,
+    pub fail_action: FailAction,
+    /// Whether to answer join requests with a CAPTCHA and approve them once it's solved.
+    pub approve_join_requests: bool,
+    pub captcha_location: CaptchaLocation,
+    pub captcha_type: CaptchaType,
+    /// Joins per minute considered as a raid, 0 disables raid detection.
+    pub raid_threshold: u16,
+    /// Joins per minute to make the chat read-only, 0 disables lockdown.
+    pub lockdown_threshold: u16,
+    pub lockdown_duration: NonZeroU64,
+    pub newcomer_policy: NewcomerPolicy,
+    /// Number of first messages of a user who passed CAPTCHA to check for spam.
+    pub newcomer_messages: u8,
+    /// Filters of messages of non-privileged members.
+    pub block_urls: bool,
+    pub block_invites: bool,
+    pub block_channel_forwards: bool,
+    pub block_stories: bool,
+    pub block_contacts: bool,
 }
 
 impl Default for Settings {
@@ -32,6 +77,23 @@ impl Default for Settings {
             message_expire: NonZeroU64::new(10).unwrap(),
             ignore_expire: NonZeroU64::new(300).unwrap(),
             delete_entry_messages: false,
+            captcha_options: 6,
+            captcha_rows: 2,
+            captcha_attempts: 1,
+            fail_action: FailAction::Mute,
+            approve_join_requests: false,
+            captcha_location: CaptchaLocation::Group,
+            captcha_type: CaptchaType::Image,
+            raid_threshold: 0,
+            lockdown_threshold: 0,
+            lockdown_duration: NonZeroU64::new(600).unwrap(),
+            newcomer_policy: NewcomerPolicy::Off,
+            newcomer_messages: 3,
+            block_urls: false,
+            block_invites: false,
+            block_channel_forwards: false,
+            block_stories: false,
+            block_contacts: false,
         }
     }
 }
@@ -44,20 +106,39 @@ impl Settings {
     pub fn message_expire(&self) -> Duration {
         Duration::from_secs(self.message_expire.get())
     }
+
+    pub fn lockdown_duration(&self) -> Duration {
+        Duration::from_secs(self.lockdown_duration.get())
+    }
+
+    /// Checks constraints between settings which can't be checked when parsing them one by one.
+    pub fn validate(&self) -> Result<(), RawSettingError> {
+        if self.captcha_rows > self.captcha_options {
+            return Err(raw_setting_error(CaptchaSizeError::TooManyRows));
+        }
+        if self.captcha_options.div_ceil(self.captcha_rows) > MAX_ROW_SIZE {
+            return Err(raw_setting_error(CaptchaSizeError::RowTooLong));
+        }
+
+        Ok(())
+    }
+}
+
+/// Error which can be explained to admins in the chat language.
+pub trait LocalizedError: error::Error + Send + Sync {
+    fn localize(&self, lang: Language) -> String;
 }
 
 #[derive(Debug)]
 pub enum RawSettingError {
-    InvalidFormat(String),
     UnknownSetting(String),
-    InvalidValue(anyhow::Error),
+    InvalidValue(Box<dyn LocalizedError>),
 }
 
 impl fmt::Display for RawSettingError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use RawSettingError as Error;
         match self {
-            Error::InvalidFormat(val) => write!(f, "string \"{val}\" has invalid format"),
             Error::UnknownSetting(key) => write!(f, "unknown setting \"{key}\""),
             Error::InvalidValue(error) => write!(f, "{error}"),
         }
@@ -66,6 +147,208 @@ impl fmt::Display for RawSettingError {
 
 impl error::Error for RawSettingError {}
 
+impl LocalizedError for RawSettingError {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::UnknownSetting(key) => {
+                t!("settings-error-unknown-setting", lang, key = key.as_str())
+            }
+            Self::InvalidValue(error) => error.localize(lang),
+        }
+    }
+}
+
+impl LocalizedError for num::ParseIntError {
+    fn localize(&self, lang: Language) -> String {
+        t!("settings-error-not-number", lang)
+    }
+}
+
+impl LocalizedError for str::ParseBoolError {
+    fn localize(&self, lang: Language) -> String {
+        t!("settings-error-not-boolean", lang)
+    }
+}
+
+impl LocalizedError for l10n::UnknownLanguageError {
+    fn localize(&self, lang: Language) -> String {
+        t!("settings-error-unknown-value", lang, value = self.value())
+    }
+}
+
+#[derive(Debug)]
+enum CaptchaSizeError {
+    TooManyRows,
+    RowTooLong,
+}
+
+impl fmt::Display for CaptchaSizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyRows => write!(f, "captcha_rows must not be greater than captcha_options"),
+            Self::RowTooLong => write!(
+                f,
+                "captcha_options must fit in captcha_rows of {MAX_ROW_SIZE} buttons"
+            ),
+        }
+    }
+}
+
+impl error::Error for CaptchaSizeError {}
+
+impl LocalizedError for CaptchaSizeError {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::TooManyRows => t!("settings-error-too-many-rows", lang),
+            Self::RowTooLong => t!("settings-error-row-too-long", lang, size = MAX_ROW_SIZE),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct OutOfRangeError {
+    min: u64,
+    max: u64,
+}
+
+impl fmt::Display for OutOfRangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "value must be between {} and {}", self.min, self.max)
+    }
+}
+
+impl error::Error for OutOfRangeError {}
+
+impl LocalizedError for OutOfRangeError {
+    fn localize(&self, lang: Language) -> String {
+        t!(
+            "settings-error-out-of-range",
+            lang,
+            min = self.min,
+            max = self.max
+        )
+    }
+}
+
+fn parse_in_range(value: &str, min: u8, max: u8) -> Result<u8, RawSettingError> {
+    let value = value.parse::<u8>().map_err(raw_setting_error)?;
+    if value < min || value > max {
+        let error = OutOfRangeError {
+            min: min.into(),
+            max: max.into(),
+        };
+        return Err(raw_setting_error(error));
+    }
+
+    Ok(value)
+}
+
+#[derive(Debug)]
+pub struct InvalidDurationError(String);
+
+impl fmt::Display for InvalidDurationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid duration \"{}\", use seconds or units like 90s, 5m, 1h30m, 2d",
+            self.0
+        )
+    }
+}
+
+impl error::Error for InvalidDurationError {}
+
+impl LocalizedError for InvalidDurationError {
+    fn localize(&self, lang: Language) -> String {
+        t!(
+            "settings-error-invalid-duration",
+            lang,
+            value = self.0.as_str()
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct DurationOutOfRangeError {
+    min: u64,
+    max: u64,
+}
+
+impl fmt::Display for DurationOutOfRangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "duration must be between {} and {}",
+            format_duration(self.min),
+            format_duration(self.max)
+        )
+    }
+}
+
+impl error::Error for DurationOutOfRangeError {}
+
+impl LocalizedError for DurationOutOfRangeError {
+    fn localize(&self, lang: Language) -> String {
+        t!(
+            "settings-error-duration-out-of-range",
+            lang,
+            min = self.min,
+            max = self.max
+        )
+    }
+}
+
+/// Parses number of seconds or a duration like "1h30m", units must go from days to seconds.
+fn parse_duration(value: &str, min: u64, max: u64) -> Result<NonZeroU64, RawSettingError> {
+    let secs = duration_secs(&value.to_lowercase())
+        .ok_or_else(|| raw_setting_error(InvalidDurationError(value.into())))?;
+    if secs < min || secs > max {
+        return Err(raw_setting_error(DurationOutOfRangeError { min, max }));
+    }
+
+    Ok(NonZeroU64::new(secs).unwrap())
+}
+
+fn duration_secs(value: &str) -> Option<u64> {
+    if value.is_empty() {
+        return None;
+    }
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(secs);
+    }
+
+    let mut secs: u64 = 0;
+    let mut units = DURATION_UNITS.iter();
+    let mut rest = value;
+    while !rest.is_empty() {
+        let digits = rest.find(|ch: char| !ch.is_ascii_digit())?;
+        let number = rest[..digits].parse::<u64>().ok()?;
+        let unit = rest[digits..].chars().next()?;
+        // Skips larger units, so each unit is used once and in order.
+        let (_, size) = units.find(|(name, _)| *name == unit)?;
+        secs = secs.checked_add(number.checked_mul(*size)?)?;
+        rest = &rest[digits + unit.len_utf8()..];
+    }
+
+    Some(secs)
+}
+
+/// Formats duration as accepted by `parse_duration`, e.g. "1h30m".
+fn format_duration(secs: u64) -> String {
+    let mut rest = secs;
+    let mut result = String::new();
+    for (unit, size) in DURATION_UNITS {
+        if rest >= size {
+            result.push_str(&format!("{}{unit}", rest / size));
+            rest %= size;
+        }
+    }
+    if result.is_empty() {
+        result.push_str("0s");
+    }
+    result
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum RawSetting {
     Language(Language),
@@ -74,88 +357,286 @@ pub enum RawSetting {
     MessageExpire(NonZeroU64),
     IgnoreExpire(NonZeroU64),
     DeleteEntryMessages(bool),
+    CaptchaOptions(u8),
+    CaptchaRows(u8),
+    CaptchaAttempts(u8),
+    FailAction(FailAction),
+    ApproveJoinRequests(bool),
+    CaptchaLocation(CaptchaLocation),
+    CaptchaType(CaptchaType),
+    RaidThreshold(u16),
+    LockdownThreshold(u16),
+    LockdownDuration(NonZeroU64),
+    NewcomerPolicy(NewcomerPolicy),
+    NewcomerMessages(u8),
+    BlockUrls(bool),
+    BlockInvites(bool),
+    BlockChannelForwards(bool),
+    BlockStories(bool),
+    BlockContacts(bool),
 }
 
 fn raw_setting_error<E>(error: E) -> RawSettingError
 where
-    E: error::Error + Send + Sync + 'static,
+    E: LocalizedError + 'static,
 {
-    RawSettingError::InvalidValue(error.into())
+    RawSettingError::InvalidValue(Box::new(error))
 }
 
-impl RawSetting {
-    pub fn from_str(s: &str) -> Result<HashSet<RawSetting>, RawSettingError> {
-        let mut settings = HashSet::new();
+/// Settings in the order they're shown to admins.
+pub const KEYS: [&str; 23] = [
+    "language",
+    "ban_channels",
+    "captcha_expire",
+    "message_expire",
+    "ignore_expire",
+    "delete_entry_messages",
+    "captcha_options",
+    "captcha_rows",
+    "captcha_attempts",
+    "fail_action",
+    "approve_join_requests",
+    "captcha_location",
+    "captcha_type",
+    "raid_threshold",
+    "lockdown_threshold",
+    "lockdown_duration",
+    "newcomer_policy",
+    "newcomer_messages",
+    "block_urls",
+    "block_invites",
+    "block_channel_forwards",
+    "block_stories",
+    "block_contacts",
+];
+
+/// How a setting is changed in the settings menu.
+pub enum Choice {
+    /// Boolean setting switched by a single button.
+    Toggle,
+    /// One of the `options`, or a value sent by admin if `custom` is set.
+    Options { options: Vec<String>, custom: bool },
+}
 
-        for line in s.lines() {
-            let line = line.trim();
-            if line == "" {
-                continue;
+fn options<T: ToString>(values: &[T], custom: bool) -> Choice {
+    Choice::Options {
+        options: values.iter().map(ToString::to_string).collect(),
+        custom,
+    }
+}
+
+impl RawSetting {
+    pub fn parse(key: &str, value: &str) -> Result<RawSetting, RawSettingError> {
+        let value = value.trim();
+        let setting = match key {
+            "language" => {
+                let value = value.parse::<Language>().map_err(raw_setting_error)?;
+                RawSetting::Language(value)
             }
-            let key_value = line.split_once(":");
-            if key_value.is_none() {
-                return Err(RawSettingError::InvalidFormat(line.into()));
+            "ban_channels" => {
+                let value = value.parse::<bool>().map_err(raw_setting_error)?;
+                RawSetting::BanChannels(value)
             }
-            let (key, value) = key_value
-                .map(|(key, value)| (key.trim(), value.trim()))
-                .unwrap();
-
-            match key {
-                "language" => {
-                    let value = value.parse::<Language>().map_err(raw_setting_error)?;
-                    settings.insert(RawSetting::Language(value));
-                }
-                "ban_channels" => {
-                    let value = value.parse::<bool>().map_err(raw_setting_error)?;
-                    settings.insert(RawSetting::BanChannels(value));
-                }
-                "captcha_expire" => {
-                    let value = value.parse::<NonZeroU64>().map_err(raw_setting_error)?;
-                    settings.insert(RawSetting::CaptchaExpire(value));
-                }
-                "message_expire" => {
-                    let value = value.parse::<NonZeroU64>().map_err(raw_setting_error)?;
-                    settings.insert(RawSetting::MessageExpire(value));
-                }
-                "ignore_expire" => {
-                    let value = value.parse::<NonZeroU64>().map_err(raw_setting_error)?;
-                    settings.insert(RawSetting::IgnoreExpire(value));
-                }
-                "delete_entry_messages" => {
-                    let value = value.parse::<bool>().map_err(raw_setting_error)?;
-                    settings.insert(RawSetting::DeleteEntryMessages(value));
-                }
-                _ => return Err(RawSettingError::UnknownSetting(key.into())),
+            "captcha_expire" => {
+                let value = parse_duration(value, MIN_CAPTCHA_EXPIRE, MAX_MESSAGE_EXPIRE)?;
+                RawSetting::CaptchaExpire(value)
             }
-        }
+            "message_expire" => {
+                let value = parse_duration(value, 1, MAX_MESSAGE_EXPIRE)?;
+                RawSetting::MessageExpire(value)
+            }
+            "ignore_expire" => {
+                let value = parse_duration(value, 1, MAX_BAN_DURATION)?;
+                RawSetting::IgnoreExpire(value)
+            }
+            "delete_entry_messages" => {
+                let value = value.parse::<bool>().map_err(raw_setting_error)?;
+                RawSetting::DeleteEntryMessages(value)
+            }
+            "captcha_options" => {
+                let value = parse_in_range(value, MIN_CAPTCHA_OPTIONS, MAX_CAPTCHA_OPTIONS)?;
+                RawSetting::CaptchaOptions(value)
+            }
+            "captcha_rows" => {
+                let value = parse_in_range(value, 1, MAX_CAPTCHA_ROWS)?;
+                RawSetting::CaptchaRows(value)
+            }
+            "captcha_attempts" => {
+                let value = parse_in_range(value, 1, MAX_CAPTCHA_ATTEMPTS)?;
+                RawSetting::CaptchaAttempts(value)
+            }
+            "fail_action" => {
+                let value = value.parse::<FailAction>().map_err(raw_setting_error)?;
+                RawSetting::FailAction(value)
+            }
+            "approve_join_requests" => {
+                let value = value.parse::<bool>().map_err(raw_setting_error)?;
+                RawSetting::ApproveJoinRequests(value)
+            }
+            "captcha_location" => {
+                let value = value
+                    .parse::<CaptchaLocation>()
+                    .map_err(raw_setting_error)?;
+                RawSetting::CaptchaLocation(value)
+            }
+            "captcha_type" => {
+                let value = value.parse::<CaptchaType>().map_err(raw_setting_error)?;
+                RawSetting::CaptchaType(value)
+            }
+            "raid_threshold" => {
+                let value = value.parse::<u16>().map_err(raw_setting_error)?;
+                RawSetting::RaidThreshold(value)
+            }
+            "lockdown_threshold" => {
+                let value = value.parse::<u16>().map_err(raw_setting_error)?;
+                RawSetting::LockdownThreshold(value)
+            }
+            "lockdown_duration" => {
+                let value = parse_duration(value, 60, MAX_LOCKDOWN_DURATION)?;
+                RawSetting::LockdownDuration(value)
+            }
+            "newcomer_policy" => {
+                let value = value.parse::<NewcomerPolicy>().map_err(raw_setting_error)?;
+                RawSetting::NewcomerPolicy(value)
+            }
+            "newcomer_messages" => {
+                let value = parse_in_range(value, 1, MAX_NEWCOMER_MESSAGES)?;
+                RawSetting::NewcomerMessages(value)
+            }
+            "block_urls" => {
+                let value = value.parse::<bool>().map_err(raw_setting_error)?;
+                RawSetting::BlockUrls(value)
+            }
+            "block_invites" => {
+                let value = value.parse::<bool>().map_err(raw_setting_error)?;
+                RawSetting::BlockInvites(value)
+            }
+            "block_channel_forwards" => {
+                let value = value.parse::<bool>().map_err(raw_setting_error)?;
+                RawSetting::BlockChannelForwards(value)
+            }
+            "block_stories" => {
+                let value = value.parse::<bool>().map_err(raw_setting_error)?;
+                RawSetting::BlockStories(value)
+            }
+            "block_contacts" => {
+                let value = value.parse::<bool>().map_err(raw_setting_error)?;
+                RawSetting::BlockContacts(value)
+            }
+            _ => return Err(RawSettingError::UnknownSetting(key.into())),
+        };
 
-        Ok(settings)
+        Ok(setting)
     }
 
-    pub fn to_string(settings: &Settings) -> String {
-        let mut lines = Vec::with_capacity(6);
-        lines.push(format!("language: <code>{}</code>", settings.language));
-        lines.push(format!(
-            "ban_channels: <code>{}</code>",
-            settings.ban_channels.is_some()
-        ));
-        lines.push(format!(
-            "captcha_expire: <code>{}</code>",
-            settings.captcha_expire
-        ));
-        lines.push(format!(
-            "message_expire: <code>{}</code>",
-            settings.message_expire
-        ));
-        lines.push(format!(
-            "ignore_expire: <code>{}</code>",
-            settings.ignore_expire
-        ));
-        lines.push(format!(
-            "delete_entry_messages: <code>{}</code>",
-            settings.delete_entry_messages
-        ));
-        lines.join("\n")
+    /// Current value of the setting in the form accepted by `parse`.
+    pub fn value(settings: &Settings, key: &str) -> Option<String> {
+        let value = match key {
+            "language" => settings.language.to_string(),
+            "ban_channels" => settings.ban_channels.is_some().to_string(),
+            "captcha_expire" => format_duration(settings.captcha_expire.get()),
+            "message_expire" => format_duration(settings.message_expire.get()),
+            "ignore_expire" => format_duration(settings.ignore_expire.get()),
+            "delete_entry_messages" => settings.delete_entry_messages.to_string(),
+            "captcha_options" => settings.captcha_options.to_string(),
+            "captcha_rows" => settings.captcha_rows.to_string(),
+            "captcha_attempts" => settings.captcha_attempts.to_string(),
+            "fail_action" => match settings.fail_action {
+                FailAction::BanFor(secs) => format!("ban_for:{}", format_duration(secs.get())),
+                action => action.to_string(),
+            },
+            "approve_join_requests" => settings.approve_join_requests.to_string(),
+            "captcha_location" => settings.captcha_location.to_string(),
+            "captcha_type" => settings.captcha_type.to_string(),
+            "raid_threshold" => settings.raid_threshold.to_string(),
+            "lockdown_threshold" => settings.lockdown_threshold.to_string(),
+            "lockdown_duration" => format_duration(settings.lockdown_duration.get()),
+            "newcomer_policy" => settings.newcomer_policy.to_string(),
+            "newcomer_messages" => settings.newcomer_messages.to_string(),
+            "block_urls" => settings.block_urls.to_string(),
+            "block_invites" => settings.block_invites.to_string(),
+            "block_channel_forwards" => settings.block_channel_forwards.to_string(),
+            "block_stories" => settings.block_stories.to_string(),
+            "block_contacts" => settings.block_contacts.to_string(),
+            _ => return None,
+        };
+
+        Some(value)
+    }
+
+    /// Value of the setting to show to admins, durations are spelled out in the chat language.
+    pub fn display(settings: &Settings, key: &str) -> Option<String> {
+        let secs = match key {
+            "raid_threshold" | "lockdown_threshold" if Self::value(settings, key)? == "0" => {
+                return Some(t!("settings-value-off", settings.language));
+            }
+            "fail_action" => match settings.fail_action {
+                FailAction::BanFor(secs) => {
+                    let duration = secs.get();
+                    return Some(t!("settings-value-ban-for", settings.language, duration));
+                }
+                _ => return Self::value(settings, key),
+            },
+            "captcha_expire" => settings.captcha_expire,
+            "message_expire" => settings.message_expire,
+            "ignore_expire" => settings.ignore_expire,
+            "lockdown_duration" => settings.lockdown_duration,
+            _ => return Self::value(settings, key),
+        };
+
+        Some(l10n::format_duration(settings.language, secs.get()))
+    }
+
+    pub fn choice(key: &str) -> Option<Choice> {
+        let choice = match key {
+            "ban_channels"
+            | "delete_entry_messages"
+            | "approve_join_requests"
+            | "block_urls"
+            | "block_invites"
+            | "block_channel_forwards"
+            | "block_stories"
+            | "block_contacts" => Choice::Toggle,
+            "language" => options(Language::values(), false),
+            "captcha_expire" => options(&["30s", "1m", "2m", "5m"], true),
+            "message_expire" => options(&["5s", "10s", "30s", "1m"], true),
+            "ignore_expire" => options(&["1m", "5m", "1h", "1d"], true),
+            "captcha_options" => {
+                let values: Vec<_> = (MIN_CAPTCHA_OPTIONS..=MAX_CAPTCHA_OPTIONS).collect();
+                options(&values, false)
+            }
+            "captcha_rows" => {
+                let values: Vec<_> = (1..=MAX_CAPTCHA_ROWS).collect();
+                options(&values, false)
+            }
+            "captcha_attempts" => {
+                let values: Vec<_> = (1..=MAX_CAPTCHA_ATTEMPTS).collect();
+                options(&values, false)
+            }
+            "fail_action" => options(&["mute", "kick", "ban", "ban_for:1d"], true),
+            "captcha_location" => {
+                options(&[CaptchaLocation::Group, CaptchaLocation::Private], false)
+            }
+            "captcha_type" => options(
+                &[CaptchaType::Image, CaptchaType::Rendered, CaptchaType::Text],
+                false,
+            ),
+            "raid_threshold" | "lockdown_threshold" => options(&[0, 10, 20, 50], true),
+            "lockdown_duration" => options(&["5m", "10m", "30m", "1h"], true),
+            "newcomer_policy" => options(
+                &[
+                    NewcomerPolicy::Off,
+                    NewcomerPolicy::Delete,
+                    NewcomerPolicy::Kick,
+                    NewcomerPolicy::Ban,
+                ],
+                false,
+            ),
+            "newcomer_messages" => options(&[1, 3, 5, 10], true),
+            _ => return None,
+        };
+
+        Some(choice)
     }
 }
 
@@ -184,6 +665,236 @@ impl From<&BanChannels> for i64 {
     }
 }
 
+/// What to do with a user who failed to answer CAPTCHA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FailAction {
+    Mute,
+    Kick,
+    Ban,
+    BanFor(NonZeroU64),
+}
+
+impl fmt::Display for FailAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mute => write!(f, "mute"),
+            Self::Kick => write!(f, "kick"),
+            Self::Ban => write!(f, "ban"),
+            Self::BanFor(secs) => write!(f, "ban_for:{secs}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FailActionError {
+    Unknown(String),
+    InvalidDuration(RawSettingError),
+}
+
+impl fmt::Display for FailActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown(value) => write!(f, "unknown fail action \"{value}\""),
+            Self::InvalidDuration(error) => write!(f, "invalid ban duration: {error}"),
+        }
+    }
+}
+
+impl error::Error for FailActionError {}
+
+impl LocalizedError for FailActionError {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::Unknown(value) => {
+                t!("settings-error-unknown-value", lang, value = value.as_str())
+            }
+            Self::InvalidDuration(error) => t!(
+                "settings-error-invalid-ban-duration",
+                lang,
+                error = error.localize(lang)
+            ),
+        }
+    }
+}
+
+impl str::FromStr for FailAction {
+    type Err = FailActionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("ban_for", duration)) => {
+                let secs = parse_duration(duration.trim(), MIN_BAN_DURATION, MAX_BAN_DURATION)
+                    .map_err(FailActionError::InvalidDuration)?;
+                Ok(Self::BanFor(secs))
+            }
+            None if s == "mute" => Ok(Self::Mute),
+            None if s == "kick" => Ok(Self::Kick),
+            None if s == "ban" => Ok(Self::Ban),
+            _ => Err(FailActionError::Unknown(s.into())),
+        }
+    }
+}
+
+/// Where CAPTCHA of a new member is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CaptchaLocation {
+    Group,
+    /// Only a notice with a link to the bot is shown in the group, CAPTCHA itself is sent in private.
+    Private,
+}
+
+impl fmt::Display for CaptchaLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Group => write!(f, "group"),
+            Self::Private => write!(f, "private"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownCaptchaLocationError(String);
+
+impl fmt::Display for UnknownCaptchaLocationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown captcha location \"{}\"", self.0)
+    }
+}
+
+impl error::Error for UnknownCaptchaLocationError {}
+
+impl LocalizedError for UnknownCaptchaLocationError {
+    fn localize(&self, lang: Language) -> String {
+        t!(
+            "settings-error-unknown-value",
+            lang,
+            value = self.0.as_str()
+        )
+    }
+}
+
+impl str::FromStr for CaptchaLocation {
+    type Err = UnknownCaptchaLocationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "group" => Ok(Self::Group),
+            "private" => Ok(Self::Private),
+            value => Err(UnknownCaptchaLocationError(value.into())),
+        }
+    }
+}
+
+/// What a user is asked to answer in CAPTCHA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CaptchaType {
+    /// Picture of one of the emojis, falls back to `Text` when no picture can be fetched.
+    Image,
+    /// Picture of an arithmetic example rendered by the bot.
+    Rendered,
+    /// Arithmetic example, doesn't depend on image providers.
+    Text,
+}
+
+impl fmt::Display for CaptchaType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Image => write!(f, "image"),
+            Self::Rendered => write!(f, "rendered"),
+            Self::Text => write!(f, "text"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownCaptchaTypeError(String);
+
+impl fmt::Display for UnknownCaptchaTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown captcha type \"{}\"", self.0)
+    }
+}
+
+impl error::Error for UnknownCaptchaTypeError {}
+
+impl LocalizedError for UnknownCaptchaTypeError {
+    fn localize(&self, lang: Language) -> String {
+        t!(
+            "settings-error-unknown-value",
+            lang,
+            value = self.0.as_str()
+        )
+    }
+}
+
+impl str::FromStr for CaptchaType {
+    type Err = UnknownCaptchaTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "image" => Ok(Self::Image),
+            "rendered" => Ok(Self::Rendered),
+            "text" => Ok(Self::Text),
+            value => Err(UnknownCaptchaTypeError(value.into())),
+        }
+    }
+}
+
+/// What to do with a user who posted spam among the first messages after passing CAPTCHA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NewcomerPolicy {
+    Off,
+    Delete,
+    Kick,
+    Ban,
+}
+
+impl fmt::Display for NewcomerPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => write!(f, "off"),
+            Self::Delete => write!(f, "delete"),
+            Self::Kick => write!(f, "kick"),
+            Self::Ban => write!(f, "ban"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownNewcomerPolicyError(String);
+
+impl fmt::Display for UnknownNewcomerPolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown newcomer policy \"{}\"", self.0)
+    }
+}
+
+impl error::Error for UnknownNewcomerPolicyError {}
+
+impl LocalizedError for UnknownNewcomerPolicyError {
+    fn localize(&self, lang: Language) -> String {
+        t!(
+            "settings-error-unknown-value",
+            lang,
+            value = self.0.as_str()
+        )
+    }
+}
+
+impl str::FromStr for NewcomerPolicy {
+    type Err = UnknownNewcomerPolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "delete" => Ok(Self::Delete),
+            "kick" => Ok(Self::Kick),
+            "ban" => Ok(Self::Ban),
+            value => Err(UnknownNewcomerPolicyError(value.into())),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct UserTagNotPresentError;
 
@@ -195,6 +906,12 @@ impl fmt::Display for UserTagNotPresentError {
 
 impl error::Error for UserTagNotPresentError {}
 
+impl LocalizedError for UserTagNotPresentError {
+    fn localize(&self, lang: Language) -> String {
+        t!("settings-error-no-user-tag", lang)
+    }
+}
+
 pub struct RawGreeting(pub String);
 
 impl str::FromStr for RawGreeting {
@@ -209,6 +926,95 @@ impl str::FromStr for RawGreeting {
     }
 }
 
+/// Maximum length in bytes of a custom question emoji (callback data is limited to 64 bytes).
+const MAX_EMOJI_LEN: usize = 32;
+
+#[derive(Debug)]
+pub enum RawQuestionError {
+    InvalidFormat,
+    EmptyEmoji,
+    EmptyPhrase,
+    EmojiTooLong,
+    NotEmoji,
+}
+
+impl fmt::Display for RawQuestionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use RawQuestionError as Error;
+        match self {
+            Error::InvalidFormat => write!(f, "string must be in \"emoji: phrase\" format"),
+            Error::EmptyEmoji => write!(f, "emoji must not be empty"),
+            Error::EmptyPhrase => write!(f, "phrase must not be empty"),
+            Error::EmojiTooLong => write!(f, "emoji must be at most {MAX_EMOJI_LEN} bytes long"),
+            Error::NotEmoji => write!(f, "emoji must be a single emoji"),
+        }
+    }
+}
+
+impl error::Error for RawQuestionError {}
+
+impl LocalizedError for RawQuestionError {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::InvalidFormat => t!("settings-error-question-format", lang),
+            Self::EmptyEmoji => t!("settings-error-empty-emoji", lang),
+            Self::EmptyPhrase => t!("settings-error-empty-phrase", lang),
+            Self::EmojiTooLong => t!("settings-error-emoji-too-long", lang, max = MAX_EMOJI_LEN),
+            Self::NotEmoji => t!("settings-error-not-emoji", lang),
+        }
+    }
+}
+
+pub struct RawQuestion {
+    pub emoji: String,
+    pub phrase: String,
+}
+
+impl RawQuestion {
+    pub fn parse_emoji(s: &str) -> Result<String, RawQuestionError> {
+        let emoji = s.trim();
+        if emoji.is_empty() {
+            Err(RawQuestionError::EmptyEmoji)
+        } else if emoji.len() > MAX_EMOJI_LEN {
+            Err(RawQuestionError::EmojiTooLong)
+        } else if !is_single_emoji(emoji) {
+            Err(RawQuestionError::NotEmoji)
+        } else {
+            Ok(emoji.to_owned())
+        }
+    }
+}
+
+/// Whether the string is one grapheme which isn't a plain letter, digit or ASCII symbol,
+/// e.g. "🦀", "👍🏽" and "1️⃣" are, but "ab", "я" and ":" aren't.
+fn is_single_emoji(s: &str) -> bool {
+    let mut graphemes = s.graphemes(true);
+    match (graphemes.next(), graphemes.next()) {
+        (Some(grapheme), None) => !grapheme
+            .chars()
+            .all(|c| c.is_ascii() || c.is_alphanumeric()),
+        _ => false,
+    }
+}
+
+impl str::FromStr for RawQuestion {
+    type Err = RawQuestionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (emoji, phrase) = s.split_once(':').ok_or(RawQuestionError::InvalidFormat)?;
+        let emoji = Self::parse_emoji(emoji)?;
+        let phrase = phrase.trim();
+        if phrase.is_empty() {
+            return Err(RawQuestionError::EmptyPhrase);
+        }
+
+        Ok(Self {
+            emoji,
+            phrase: phrase.to_owned(),
+        })
+    }
+}
+
 pub async fn preload() -> Result<(), sqlx::Error> {
     let pool = SqlitePoolOptions::new()
         .max_connections(10)
@@ -242,6 +1048,38 @@ pub async fn preload() -> Result<(), sqlx::Error> {
             .map(|val| NonZeroU64::new(val as u64).unwrap())
             .unwrap();
         let delete_entry_messages: bool = row.get("delete_entry_messages");
+        let captcha_options: u8 = row.get("captcha_options");
+        let captcha_rows: u8 = row.get("captcha_rows");
+        let captcha_attempts: u8 = row.get("captcha_attempts");
+        let fail_action: FailAction = row
+            .try_get::<String, _>("fail_action")
+            .map(|val| val.parse().unwrap())
+            .unwrap();
+        let approve_join_requests: bool = row.get("approve_join_requests");
+        let captcha_location: CaptchaLocation = row
+            .try_get::<String, _>("captcha_location")
+            .map(|val| val.parse().unwrap())
+            .unwrap();
+        let captcha_type: CaptchaType = row
+            .try_get::<String, _>("captcha_type")
+            .map(|val| val.parse().unwrap())
+            .unwrap();
+        let raid_threshold: u16 = row.get("raid_threshold");
+        let lockdown_threshold: u16 = row.get("lockdown_threshold");
+        let lockdown_duration = row
+            .try_get::<i64, _>("lockdown_duration")
+            .map(|val| NonZeroU64::new(val as u64).unwrap())
+            .unwrap();
+        let newcomer_policy: NewcomerPolicy = row
+            .try_get::<String, _>("newcomer_policy")
+            .map(|val| val.parse().unwrap())
+            .unwrap();
+        let newcomer_messages: u8 = row.get("newcomer_messages");
+        let block_urls: bool = row.get("block_urls");
+        let block_invites: bool = row.get("block_invites");
+        let block_channel_forwards: bool = row.get("block_channel_forwards");
+        let block_stories: bool = row.get("block_stories");
+        let block_contacts: bool = row.get("block_contacts");
 
         settings.insert(
             ChatId(chat_id),
@@ -252,6 +1090,23 @@ pub async fn preload() -> Result<(), sqlx::Error> {
                 message_expire,
                 ignore_expire,
                 delete_entry_messages,
+                captcha_options,
+                captcha_rows,
+                captcha_attempts,
+                fail_action,
+                approve_join_requests,
+                captcha_location,
+                captcha_type,
+                raid_threshold,
+                lockdown_threshold,
+                lockdown_duration,
+                newcomer_policy,
+                newcomer_messages,
+                block_urls,
+                block_invites,
+                block_channel_forwards,
+                block_stories,
+                block_contacts,
             },
         );
     }
@@ -265,9 +1120,34 @@ pub async fn preload() -> Result<(), sqlx::Error> {
         greetings.insert(ChatId(row.0), row.1);
     }
 
+    let rows = sqlx::query("SELECT * FROM questions ORDER BY id")
+        .fetch_all(&pool)
+        .await?;
+
+    let mut questions: HashMap<ChatId, Vec<CustomQuery>> = HashMap::new();
+    for row in rows {
+        let chat_id: i64 = row.get("chat_id");
+        let phrase: Option<String> = row.get("phrase");
+        let file_id: Option<String> = row.get("file_id");
+        let picture = match (phrase, file_id) {
+            (_, Some(file_id)) => Picture::File(file_id),
+            (Some(phrase), None) => Picture::Phrase(phrase),
+            (None, None) => continue,
+        };
+        questions
+            .entry(ChatId(chat_id))
+            .or_default()
+            .push(CustomQuery {
+                id: row.get("id"),
+                emoji: row.get("emoji"),
+                picture,
+            });
+    }
+
     SQLITE_POOL.set(pool).unwrap();
     SETTINGS.set(Mutex::new(settings)).unwrap();
     GREETINGS.set(Mutex::new(greetings)).unwrap();
+    QUESTIONS.set(Mutex::new(questions)).unwrap();
 
     Ok(())
 }
@@ -292,23 +1172,42 @@ pub fn get_ban_channels(chat_id: ChatId) -> Option<BanChannels> {
 
 pub fn get_greeting(chat_id: ChatId) -> Option<String> {
     let greeting = GREETINGS.get().unwrap().lock().unwrap();
-    greeting.get(&chat_id).map(|val| val.clone())
+    greeting.get(&chat_id).cloned()
 }
 
-pub async fn set(chat_id: ChatId, settings: Settings) -> Result<(), sqlx::Error> {
+fn upsert_settings(
+    chat_id: ChatId,
+    settings: &Settings,
+) -> Query<'static, Sqlite, SqliteArguments<'static>> {
     let ban_channels: Option<i64> = settings.ban_channels.as_ref().map(|val| val.into());
 
-    let pool = SQLITE_POOL.get().unwrap();
     sqlx::query(
         r#"
-INSERT INTO settings VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+INSERT INTO settings VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24)
 ON CONFLICT (chat_id) DO UPDATE SET
     language = ?2,
     ban_channels = ?3,
     captcha_expire = ?4,
     message_expire = ?5,
     ignore_expire = ?6,
-    delete_entry_messages = ?7
+    delete_entry_messages = ?7,
+    captcha_options = ?8,
+    captcha_rows = ?9,
+    captcha_attempts = ?10,
+    fail_action = ?11,
+    approve_join_requests = ?12,
+    captcha_location = ?13,
+    captcha_type = ?14,
+    raid_threshold = ?15,
+    lockdown_threshold = ?16,
+    lockdown_duration = ?17,
+    newcomer_policy = ?18,
+    newcomer_messages = ?19,
+    block_urls = ?20,
+    block_invites = ?21,
+    block_channel_forwards = ?22,
+    block_stories = ?23,
+    block_contacts = ?24
         "#,
     )
     .bind(chat_id.0)
@@ -318,8 +1217,28 @@ ON CONFLICT (chat_id) DO UPDATE SET
     .bind(settings.message_expire.get() as i64)
     .bind(settings.ignore_expire.get() as i64)
     .bind(settings.delete_entry_messages)
-    .execute(pool)
-    .await?;
+    .bind(settings.captcha_options)
+    .bind(settings.captcha_rows)
+    .bind(settings.captcha_attempts)
+    .bind(settings.fail_action.to_string())
+    .bind(settings.approve_join_requests)
+    .bind(settings.captcha_location.to_string())
+    .bind(settings.captcha_type.to_string())
+    .bind(settings.raid_threshold)
+    .bind(settings.lockdown_threshold)
+    .bind(settings.lockdown_duration.get() as i64)
+    .bind(settings.newcomer_policy.to_string())
+    .bind(settings.newcomer_messages)
+    .bind(settings.block_urls)
+    .bind(settings.block_invites)
+    .bind(settings.block_channel_forwards)
+    .bind(settings.block_stories)
+    .bind(settings.block_contacts)
+}
+
+pub async fn set(chat_id: ChatId, settings: Settings) -> Result<(), sqlx::Error> {
+    let pool = SQLITE_POOL.get().unwrap();
+    upsert_settings(chat_id, &settings).execute(pool).await?;
 
     let mut hm = SETTINGS.get().unwrap().lock().unwrap();
     hm.insert(chat_id, settings);
@@ -327,6 +1246,82 @@ ON CONFLICT (chat_id) DO UPDATE SET
     Ok(())
 }
 
+/// Replaces settings, greeting and custom questions of the chat in a single transaction.
+pub async fn replace(
+    chat_id: ChatId,
+    settings: Settings,
+    greeting: Option<String>,
+    questions: Vec<(String, Picture)>,
+) -> Result<(), sqlx::Error> {
+    let pool = SQLITE_POOL.get().unwrap();
+    let mut tx = pool.begin().await?;
+    upsert_settings(chat_id, &settings)
+        .execute(&mut *tx)
+        .await?;
+
+    if let Some(greeting) = &greeting {
+        sqlx::query(
+            r#"
+INSERT INTO greetings VALUES (?1, ?2)
+ON CONFLICT (chat_id) DO UPDATE SET greeting = ?2
+            "#,
+        )
+        .bind(chat_id.0)
+        .bind(greeting)
+        .execute(&mut *tx)
+        .await?;
+    } else {
+        sqlx::query("DELETE FROM greetings WHERE chat_id = ?1")
+            .bind(chat_id.0)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    sqlx::query("DELETE FROM questions WHERE chat_id = ?1")
+        .bind(chat_id.0)
+        .execute(&mut *tx)
+        .await?;
+    let mut custom_questions = Vec::with_capacity(questions.len());
+    for (emoji, picture) in questions {
+        let (phrase, file_id) = match &picture {
+            Picture::Phrase(phrase) => (Some(phrase), None),
+            Picture::File(file_id) => (None, Some(file_id)),
+        };
+        let id = sqlx::query(
+            "INSERT INTO questions (chat_id, emoji, phrase, file_id) VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(chat_id.0)
+        .bind(&emoji)
+        .bind(phrase)
+        .bind(file_id)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        custom_questions.push(CustomQuery { id, emoji, picture });
+    }
+    tx.commit().await?;
+
+    SETTINGS
+        .get()
+        .unwrap()
+        .lock()
+        .unwrap()
+        .insert(chat_id, settings);
+    let mut greetings = GREETINGS.get().unwrap().lock().unwrap();
+    match greeting {
+        Some(greeting) => greetings.insert(chat_id, greeting),
+        None => greetings.remove(&chat_id),
+    };
+    QUESTIONS
+        .get()
+        .unwrap()
+        .lock()
+        .unwrap()
+        .insert(chat_id, custom_questions);
+
+    Ok(())
+}
+
 pub async fn set_greeting(chat_id: ChatId, greeting: String) -> Result<(), sqlx::Error> {
     let pool = SQLITE_POOL.get().unwrap();
     sqlx::query(
@@ -346,6 +1341,66 @@ ON CONFLICT (chat_id) DO UPDATE SET greeting = ?2
     Ok(())
 }
 
+pub fn get_questions(chat_id: ChatId) -> Vec<CustomQuery> {
+    let questions = QUESTIONS.get().unwrap().lock().unwrap();
+    questions.get(&chat_id).cloned().unwrap_or_default()
+}
+
+/// Whether the emoji is already used by the catalog or chat's custom questions.
+pub fn is_emoji_used(chat_id: ChatId, emoji: &str) -> bool {
+    qna::has_emoji(emoji)
+        || get_questions(chat_id)
+            .iter()
+            .any(|question| qna::same_emoji(&question.emoji, emoji))
+}
+
+pub async fn add_question(
+    chat_id: ChatId,
+    emoji: String,
+    picture: Picture,
+) -> Result<(), sqlx::Error> {
+    let (phrase, file_id) = match &picture {
+        Picture::Phrase(phrase) => (Some(phrase), None),
+        Picture::File(file_id) => (None, Some(file_id)),
+    };
+
+    let pool = SQLITE_POOL.get().unwrap();
+    let id = sqlx::query(
+        "INSERT INTO questions (chat_id, emoji, phrase, file_id) VALUES (?1, ?2, ?3, ?4)",
+    )
+    .bind(chat_id.0)
+    .bind(&emoji)
+    .bind(phrase)
+    .bind(file_id)
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    let mut hm = QUESTIONS.get().unwrap().lock().unwrap();
+    hm.entry(chat_id)
+        .or_default()
+        .push(CustomQuery { id, emoji, picture });
+
+    Ok(())
+}
+
+/// Returns `false` if the chat has no question with such id.
+pub async fn delete_question(chat_id: ChatId, id: i64) -> Result<bool, sqlx::Error> {
+    let pool = SQLITE_POOL.get().unwrap();
+    let result = sqlx::query("DELETE FROM questions WHERE chat_id = ?1 AND id = ?2")
+        .bind(chat_id.0)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    let mut hm = QUESTIONS.get().unwrap().lock().unwrap();
+    if let Some(questions) = hm.get_mut(&chat_id) {
+        questions.retain(|question| question.id != id);
+    }
+
+    Ok(result.rows_affected() > 0)
+}
+
 pub fn lang(chat_id: ChatId) -> Language {
     let settings = SETTINGS.get().unwrap().lock().unwrap();
 
@@ -355,3 +1410,151 @@ pub fn lang(chat_id: ChatId) -> Language {
         Language::default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_emoji() {
+        for emoji in ["🦀", " 🦀 ", "👍🏽", "1️⃣", "🇷🇺", "👨‍👩‍👧"] {
+            assert_eq!(RawQuestion::parse_emoji(emoji).unwrap(), emoji.trim());
+        }
+        for text in ["a", "я", "7", ":", "🦀🦀", "🦀 crab", "crab"] {
+            assert!(matches!(
+                RawQuestion::parse_emoji(text),
+                Err(RawQuestionError::NotEmoji)
+            ));
+        }
+        assert!(matches!(
+            RawQuestion::parse_emoji("  "),
+            Err(RawQuestionError::EmptyEmoji)
+        ));
+    }
+
+    #[test]
+    fn validate_captcha_size() {
+        let size = |captcha_options, captcha_rows| Settings {
+            captcha_options,
+            captcha_rows,
+            ..Settings::default()
+        };
+        assert!(Settings::default().validate().is_ok());
+        assert!(size(2, 1).validate().is_ok());
+        assert!(size(8, 1).validate().is_ok());
+        assert!(size(12, 2).validate().is_ok());
+        assert!(size(5, 4).validate().is_ok());
+        assert!(size(9, 1).validate().is_err());
+        assert!(size(12, 1).validate().is_err());
+        assert!(size(3, 4).validate().is_err());
+    }
+
+    #[test]
+    fn parse_durations() {
+        let cases = [
+            ("90s", 90),
+            ("5m", 300),
+            ("1h30m", 5400),
+            ("2d", 172800),
+            ("1d2h3m4s", 93784),
+            ("1H30M", 5400),
+            ("90", 90),
+            ("0100", 100),
+        ];
+        for (value, secs) in cases {
+            assert_eq!(duration_secs(&value.to_lowercase()), Some(secs), "{value}");
+        }
+
+        let invalid = [
+            "", " ", "s", "m5", "-5", "1.5h", "5x", "5m30", "1h 30m", "1m1h", "5m5m", "1d1d",
+        ];
+        for value in invalid {
+            assert_eq!(duration_secs(value), None, "{value:?}");
+        }
+
+        assert_eq!(duration_secs(&u64::MAX.to_string()), Some(u64::MAX));
+        assert_eq!(duration_secs("18446744073709551616"), None);
+        assert_eq!(duration_secs(&format!("{}d", u64::MAX / 86400 + 1)), None);
+        assert_eq!(
+            duration_secs(&format!("{}d1s", u64::MAX / 86400)),
+            Some(u64::MAX / 86400 * 86400 + 1)
+        );
+    }
+
+    #[test]
+    fn parse_duration_in_range() {
+        assert_eq!(parse_duration("1m", 10, 60).unwrap().get(), 60);
+        assert!(parse_duration("9s", 10, 60).is_err());
+        assert!(parse_duration("1m1s", 10, 60).is_err());
+        assert!(parse_duration("0", 1, 60).is_err());
+        assert!(parse_duration("", 1, 60).is_err());
+    }
+
+    #[test]
+    fn format_durations() {
+        let cases = [
+            (0, "0s"),
+            (1, "1s"),
+            (60, "1m"),
+            (90, "1m30s"),
+            (3600, "1h"),
+            (5400, "1h30m"),
+            (86400, "1d"),
+            (93784, "1d2h3m4s"),
+        ];
+        for (secs, value) in cases {
+            assert_eq!(format_duration(secs), value);
+        }
+    }
+
+    #[test]
+    fn duration_round_trip() {
+        for secs in [
+            1,
+            59,
+            60,
+            61,
+            3599,
+            3600,
+            3661,
+            86399,
+            86400,
+            90061,
+            MAX_BAN_DURATION,
+        ] {
+            assert_eq!(duration_secs(&format_duration(secs)), Some(secs));
+        }
+        for value in ["90s", "5m", "1h30m", "2d"] {
+            let secs = duration_secs(value).unwrap();
+            assert_eq!(duration_secs(&format_duration(secs)), Some(secs));
+        }
+        assert_eq!(format_duration(duration_secs("90s").unwrap()), "1m30s");
+    }
+
+    #[test]
+    fn parse_fail_action() {
+        let ban_for = |secs| FailAction::BanFor(NonZeroU64::new(secs).unwrap());
+        assert_eq!("ban_for:1d".parse::<FailAction>().unwrap(), ban_for(86400));
+        assert_eq!("ban_for:3600".parse::<FailAction>().unwrap(), ban_for(3600));
+        assert_eq!(
+            "ban_for: 1h30m".parse::<FailAction>().unwrap(),
+            ban_for(5400)
+        );
+        assert!("ban_for:10s".parse::<FailAction>().is_err());
+        assert!("ban_for:367d".parse::<FailAction>().is_err());
+        assert!("ban_for:soon".parse::<FailAction>().is_err());
+        assert_eq!("kick".parse::<FailAction>().unwrap(), FailAction::Kick);
+        assert!("kick:1d".parse::<FailAction>().is_err());
+    }
+
+    #[test]
+    fn fail_action_value_round_trip() {
+        let settings = Settings {
+            fail_action: FailAction::BanFor(NonZeroU64::new(90000).unwrap()),
+            ..Settings::default()
+        };
+        let value = RawSetting::value(&settings, "fail_action").unwrap();
+        assert_eq!(value, "ban_for:1d1h");
+        assert_eq!(value.parse::<FailAction>().unwrap(), settings.fail_action);
+    }
+}
@@ -0,0 +1,178 @@
+use std::fmt;
+use teloxide::types::{Message, MessageEntity, MessageEntityKind};
+
+/// Why a message looks like spam.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    Link,
+    InviteLink,
+    ChannelForward,
+    Mention,
+    Media,
+    Contact,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Link => write!(f, "link"),
+            Self::InviteLink => write!(f, "invite link"),
+            Self::ChannelForward => write!(f, "forward from a channel"),
+            Self::Mention => write!(f, "mention"),
+            Self::Media => write!(f, "media"),
+            Self::Contact => write!(f, "contact"),
+        }
+    }
+}
+
+fn entities(msg: &Message) -> impl Iterator<Item = &MessageEntity> {
+    let entities = msg.entities().unwrap_or_default();
+    let caption_entities = msg.caption_entities().unwrap_or_default();
+    entities.iter().chain(caption_entities)
+}
+
+pub fn has_link(msg: &Message) -> bool {
+    entities(msg).any(|entity| {
+        matches!(
+            entity.kind,
+            MessageEntityKind::Url | MessageEntityKind::TextLink { .. }
+        )
+    })
+}
+
+/// Whether the message has a link to join another chat, like `t.me/+abc` or `t.me/joinchat/abc`.
+pub fn has_invite_link(msg: &Message) -> bool {
+    let entities = msg.parse_entities().unwrap_or_default();
+    let caption_entities = msg.parse_caption_entities().unwrap_or_default();
+    entities
+        .iter()
+        .chain(&caption_entities)
+        .any(|entity| match entity.kind() {
+            MessageEntityKind::Url => is_invite_link(entity.text()),
+            MessageEntityKind::TextLink { url } => is_invite_link(url.as_str()),
+            _ => false,
+        })
+}
+
+fn is_invite_link(link: &str) -> bool {
+    let link = link.to_lowercase();
+    let link = link
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.");
+    if link.starts_with("tg://join") {
+        return true;
+    }
+    ["t.me/", "telegram.me/", "telegram.dog/"]
+        .iter()
+        .filter_map(|domain| link.strip_prefix(domain))
+        .any(|path| path.starts_with('+') || path.starts_with("joinchat/"))
+}
+
+pub fn is_channel_forward(msg: &Message) -> bool {
+    msg.forward_from_chat()
+        .map(|chat| chat.is_channel())
+        .unwrap_or(false)
+}
+
+/// Whether the message mentions someone by username, usually it's another chat or a bot.
+pub fn has_mention(msg: &Message) -> bool {
+    entities(msg).any(|entity| entity.kind == MessageEntityKind::Mention)
+}
+
+pub fn has_media(msg: &Message) -> bool {
+    msg.photo().is_some()
+        || msg.video().is_some()
+        || msg.animation().is_some()
+        || msg.document().is_some()
+        || msg.audio().is_some()
+        || msg.voice().is_some()
+        || msg.video_note().is_some()
+}
+
+/// Checks a message of a new member for things spammers usually post first.
+pub fn check(msg: &Message) -> Option<Reason> {
+    if has_link(msg) {
+        Some(Reason::Link)
+    } else if is_channel_forward(msg) {
+        Some(Reason::ChannelForward)
+    } else if has_mention(msg) {
+        Some(Reason::Mention)
+    } else if has_media(msg) {
+        Some(Reason::Media)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str, entity: serde_json::Value) -> Message {
+        serde_json::from_value(serde_json::json!({
+            "message_id": 1,
+            "date": 0,
+            "chat": {"id": -1001, "type": "supergroup", "title": "Group"},
+            "from": {"id": 1, "is_bot": false, "first_name": "User"},
+            "text": text,
+            "entities": [entity],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn invite_links() {
+        for link in [
+            "t.me/+AbC123",
+            "t.me/joinchat/AbC123",
+            "https://t.me/+AbC123",
+            "http://t.me/joinchat/AbC123",
+            "https://www.t.me/+AbC123",
+            "www.t.me/joinchat/AbC123",
+            "telegram.me/+AbC123",
+            "https://telegram.me/joinchat/AbC123",
+            "http://www.telegram.me/+AbC123",
+            "telegram.dog/joinchat/AbC123",
+            "HTTPS://T.ME/+AbC123",
+            "tg://join?invite=AbC123",
+        ] {
+            assert!(is_invite_link(link), "{link}");
+        }
+    }
+
+    #[test]
+    fn not_invite_links() {
+        for link in [
+            "t.me/username",
+            "https://t.me/username",
+            "https://www.telegram.me/username",
+            "t.me/username/+123",
+            "https://example.com/+AbC123",
+            "https://example.com/t.me/+AbC123",
+            "tg://resolve?domain=username",
+        ] {
+            assert!(!is_invite_link(link), "{link}");
+        }
+    }
+
+    #[test]
+    fn invite_link_entities() {
+        let text = "join t.me/+AbC123";
+        let url = serde_json::json!({"type": "url", "offset": 5, "length": 12});
+        assert!(has_invite_link(&message(text, url)));
+
+        let text = "join t.me/username";
+        let url = serde_json::json!({"type": "url", "offset": 5, "length": 13});
+        assert!(!has_invite_link(&message(text, url)));
+
+        let text = "join us";
+        let text_link = serde_json::json!({
+            "type": "text_link",
+            "offset": 5,
+            "length": 2,
+            "url": "https://t.me/joinchat/AbC123",
+        });
+        assert!(has_invite_link(&message(text, text_link)));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::redis;
+use futures::future::BoxFuture;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{error, fmt, marker::PhantomData, str::FromStr, sync::Arc, time::Duration};
+use teloxide::dispatching::dialogue::{InMemStorage, InMemStorageError, Storage};
+use teloxide::types::ChatId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
+    Memory,
+    Redis,
+}
+
+impl fmt::Display for StorageKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Memory => write!(f, "memory"),
+            Self::Redis => write!(f, "redis"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownStorageError(String);
+
+impl fmt::Display for UnknownStorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown dialogue storage \"{}\"", self.0)
+    }
+}
+
+impl error::Error for UnknownStorageError {}
+
+impl FromStr for StorageKind {
+    type Err = UnknownStorageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(Self::Memory),
+            "redis" => Ok(Self::Redis),
+            value => Err(UnknownStorageError(value.into())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Memory(InMemStorageError),
+    Redis(::redis::RedisError),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Memory(error) => write!(f, "in-memory storage error: {error}"),
+            Self::Redis(error) => write!(f, "Redis error: {error}"),
+            Self::Json(error) => write!(f, "invalid dialogue state: {error}"),
+        }
+    }
+}
+
+impl error::Error for StorageError {}
+
+impl From<::redis::RedisError> for StorageError {
+    fn from(value: ::redis::RedisError) -> Self {
+        Self::Redis(value)
+    }
+}
+
+impl From<InMemStorageError> for StorageError {
+    fn from(value: InMemStorageError) -> Self {
+        Self::Memory(value)
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
+
+/// Dialogue storage of the kind selected in config.
+pub enum DialogueStorage<D> {
+    Memory(Arc<InMemStorage<D>>),
+    Redis(Arc<RedisStorage<D>>),
+}
+
+impl<D> DialogueStorage<D> {
+    /// Creates dialogue storage of the given kind, `ttl` applies to Redis only.
+    pub fn new(kind: StorageKind, ttl: Duration) -> Arc<Self> {
+        let storage = match kind {
+            StorageKind::Memory => Self::Memory(InMemStorage::new()),
+            StorageKind::Redis => Self::Redis(RedisStorage::new(ttl)),
+        };
+        Arc::new(storage)
+    }
+}
+
+impl<D> Storage<D> for DialogueStorage<D>
+where
+    D: Serialize + DeserializeOwned + Clone + Send + 'static,
+{
+    type Error = StorageError;
+
+    fn remove_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            match self.as_ref() {
+                Self::Memory(storage) => Ok(storage.clone().remove_dialogue(chat_id).await?),
+                Self::Redis(storage) => storage.clone().remove_dialogue(chat_id).await,
+            }
+        })
+    }
+
+    fn update_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            match self.as_ref() {
+                Self::Memory(storage) => {
+                    Ok(storage.clone().update_dialogue(chat_id, dialogue).await?)
+                }
+                Self::Redis(storage) => storage.clone().update_dialogue(chat_id, dialogue).await,
+            }
+        })
+    }
+
+    fn get_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<Option<D>, Self::Error>> {
+        Box::pin(async move {
+            match self.as_ref() {
+                Self::Memory(storage) => Ok(storage.clone().get_dialogue(chat_id).await?),
+                Self::Redis(storage) => storage.clone().get_dialogue(chat_id).await,
+            }
+        })
+    }
+}
+
+/// Dialogues kept in Redis as JSON, so they survive restarts and are shared between replicas.
+/// Abandoned dialogues expire after `ttl` since the last update.
+pub struct RedisStorage<D> {
+    ttl: Duration,
+    dialogue: PhantomData<fn() -> D>,
+}
+
+impl<D> RedisStorage<D> {
+    pub fn new(ttl: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            ttl,
+            dialogue: PhantomData,
+        })
+    }
+}
+
+impl<D> Storage<D> for RedisStorage<D>
+where
+    D: Serialize + DeserializeOwned + Send + 'static,
+{
+    type Error = StorageError;
+
+    /// Unlike `InMemStorage` it doesn't fail on missing dialogue, it may have just expired.
+    fn remove_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            redis::delete_dialogue(chat_id).await?;
+            Ok(())
+        })
+    }
+
+    fn update_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            let data = serde_json::to_string(&dialogue)?;
+            redis::set_dialogue(chat_id, data, self.ttl).await?;
+            Ok(())
+        })
+    }
+
+    fn get_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<Option<D>, Self::Error>> {
+        Box::pin(async move {
+            let dialogue = match redis::get_dialogue(chat_id).await? {
+                Some(data) => Some(serde_json::from_str(&data)?),
+                None => None,
+            };
+            Ok(dialogue)
+        })
+    }
+}
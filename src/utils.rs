@@ -1,21 +1,23 @@
-use teloxide::types::{ChatId, InlineKeyboardButton, InlineKeyboardMarkup, MessageId, True};
-use teloxide::{prelude::Requester, requests::ResponseResult};
-use tokio::{
-    task::JoinHandle,
-    time::{sleep, Duration},
+use crate::redis;
+use crate::settings::FailAction;
+use chrono::Utc;
+use teloxide::prelude::*;
+use teloxide::types::{
+    ChatId, ChatPermissions, InlineKeyboardButton, InlineKeyboardMarkup, MessageId, UserId,
 };
+use tokio::time::{sleep, Duration};
 
-pub fn emojis_keyboard(emojis: &[&str], rows: usize) -> InlineKeyboardMarkup {
+/// Spreads buttons over `rows` rows, the first rows get one button more if they can't be equal.
+pub fn emojis_keyboard(emojis: &[String], rows: usize, prefix: &str) -> InlineKeyboardMarkup {
+    let rows = rows.clamp(1, emojis.len().max(1));
     let mut keyboard: Vec<Vec<InlineKeyboardButton>> = Vec::with_capacity(rows);
-    let row_size = (emojis.len() as f64 / rows as f64).ceil() as usize;
+    let mut buttons = emojis
+        .iter()
+        .map(|emoji| InlineKeyboardButton::callback(emoji.to_owned(), format!("{prefix}{emoji}")));
 
-    for row in emojis.chunks(row_size) {
-        let kb_row = row
-            .iter()
-            .map(|emoji| InlineKeyboardButton::callback(emoji.to_owned(), emoji.to_owned()))
-            .collect();
-
-        keyboard.push(kb_row);
+    for row in 0..rows {
+        let row_size = emojis.len() / rows + usize::from(row < emojis.len() % rows);
+        keyboard.push(buttons.by_ref().take(row_size).collect());
     }
 
     InlineKeyboardMarkup::new(keyboard)
@@ -26,33 +28,107 @@ pub fn delete_message_later(
     chat_id: ChatId,
     message_id: MessageId,
     timeout: Duration,
-) -> JoinHandle<ResponseResult<True>> {
+) {
     let bot = bot.clone();
     tokio::spawn(async move {
         sleep(timeout).await;
-        bot.delete_message(chat_id, message_id).await
-    })
+        let _ = bot.delete_message(chat_id, message_id).await;
+    });
 }
 
-pub fn delete_captcha_later(
+/// Punishes a user who didn't pass CAPTCHA, muted users stay restricted.
+pub async fn apply_fail_action(
     bot: &crate::Bot,
     chat_id: ChatId,
-    captcha_message_id: MessageId,
-    text: String,
-    captcha_timeout: Duration,
-    service_message_timeout: Duration,
-) -> JoinHandle<()> {
-    let bot = bot.clone();
-    tokio::spawn(async move {
-        // Wait while CAPTCHA becomes expired, delete it and show temporary service message.
-        sleep(captcha_timeout).await;
-        let result = bot.delete_message(chat_id, captcha_message_id).await;
-        // Show service message only when CAPTCHA was deleted.
-        if result.is_ok() {
-            if let Ok(message) = bot.send_message(chat_id, text).await {
-                sleep(service_message_timeout).await;
-                let _ = bot.delete_message(chat_id, message.id).await;
+    user_id: UserId,
+    fail_action: FailAction,
+) {
+    let result = match fail_action {
+        FailAction::Mute => return,
+        FailAction::Kick => {
+            let result = bot.ban_chat_member(chat_id, user_id).await;
+            if result.is_ok() {
+                bot.unban_chat_member(chat_id, user_id)
+                    .only_if_banned(true)
+                    .await
+            } else {
+                result
             }
         }
-    })
+        FailAction::Ban => bot.ban_chat_member(chat_id, user_id).await,
+        FailAction::BanFor(secs) => {
+            let until_date = Utc::now() + chrono::Duration::seconds(secs.get() as i64);
+            bot.ban_chat_member(chat_id, user_id)
+                .until_date(until_date)
+                .await
+        }
+    };
+
+    if let Err(error) = result {
+        log::error!("Failed to {fail_action} user {user_id} in chat {chat_id}: {error}");
+    }
+}
+
+/// Default permissions of the chat members, all permissions if they couldn't be fetched.
+pub async fn default_permissions(bot: &crate::Bot, chat_id: ChatId) -> ChatPermissions {
+    // Chat is read-only during lockdown, its usual permissions are saved.
+    match redis::get_lockdown(chat_id).await {
+        Ok(Some(permissions)) => return permissions,
+        Ok(None) => {}
+        Err(error) => log::error!("Failed to get lockdown of chat {chat_id}: {error}"),
+    }
+
+    match bot.get_chat(chat_id).await {
+        Ok(chat) => chat.permissions().unwrap_or(ChatPermissions::all()),
+        Err(error) => {
+            log::error!("Failed to get permissions of chat {chat_id}: {error}");
+            ChatPermissions::all()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use teloxide::types::InlineKeyboardButtonKind;
+
+    fn row_sizes(options: usize, rows: usize) -> Vec<usize> {
+        let emojis: Vec<_> = (0..options).map(|i| i.to_string()).collect();
+        emojis_keyboard(&emojis, rows, "p:")
+            .inline_keyboard
+            .iter()
+            .map(|row| row.len())
+            .collect()
+    }
+
+    #[test]
+    fn keyboard_has_all_rows() {
+        assert_eq!(row_sizes(5, 4), [2, 1, 1, 1]);
+        assert_eq!(row_sizes(6, 2), [3, 3]);
+        assert_eq!(row_sizes(7, 3), [3, 2, 2]);
+        assert_eq!(row_sizes(12, 4), [3, 3, 3, 3]);
+        assert_eq!(row_sizes(2, 1), [2]);
+        for options in 2..=12 {
+            for rows in 1..=options.min(4) {
+                let sizes = row_sizes(options, rows);
+                assert_eq!(sizes.len(), rows);
+                assert_eq!(sizes.iter().sum::<usize>(), options);
+                assert!(sizes.iter().max().unwrap() - sizes.iter().min().unwrap() <= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn keyboard_keeps_order_and_prefix() {
+        let emojis = ["🦀".to_owned(), "🦞".to_owned(), "🦑".to_owned()];
+        let keyboard = emojis_keyboard(&emojis, 2, "1:2:");
+        let buttons: Vec<_> = keyboard.inline_keyboard.concat();
+        for (button, emoji) in buttons.iter().zip(&emojis) {
+            assert_eq!(&button.text, emoji);
+            assert!(
+                matches!(&button.kind, InlineKeyboardButtonKind::CallbackData(data) if *data == format!("1:2:{emoji}"))
+            );
+        }
+        assert_eq!(buttons.len(), emojis.len());
+    }
 }